@@ -0,0 +1,170 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{bias, CongestionController};
+
+// Ticks the bottleneck bandwidth is the max over
+const BW_WINDOW: usize = 10;
+
+// Experimental BBR-like controller, sizing the window by the bandwidth-delay product
+//
+// The bottleneck bandwidth is the highest delivery rate of the last BW_WINDOW ticks,
+// the propagation delay the lowest RTT seen, and losses aren't taken as congestion at all.
+// Next to the window it gives a pacing interval, so transmissions can be spread at that bandwidth
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bbr {
+    // Delivery rates of the last ticks, in bytes per second, newest last
+    pub rates: VecDeque<f64>,
+    pub min_rtt: Option<Duration>,
+    // Average message size in bytes
+    pub message_size: Option<f64>,
+
+    // Window before there is a bandwidth estimate
+    pub initial_window: usize,
+    // Multiple of the BDP allowed in flight
+    pub window_gain: f64,
+    // Multiple of the bottleneck bandwidth to pace at
+    pub pacing_gain: f64,
+
+    // Bytes acked and the longest RTT among them this tick
+    pub tick_bytes: usize,
+    pub tick_rtt: Duration,
+}
+
+impl Bbr {
+    pub fn new(initial_window: usize) -> Self {
+        Bbr {
+            rates: VecDeque::new(),
+            min_rtt: None,
+            message_size: None,
+            initial_window: initial_window.max(1),
+            window_gain: 2.0,
+            pacing_gain: 1.0,
+            tick_bytes: 0,
+            tick_rtt: Duration::ZERO,
+        }
+    }
+
+    // Bottleneck bandwidth estimate in bytes per second
+    pub fn bandwidth(&self) -> Option<f64> {
+        self.rates.iter().copied().reduce(f64::max)
+    }
+
+    // Bandwidth-delay product estimate in bytes
+    pub fn bdp(&self) -> Option<f64> {
+        Some(self.bandwidth()? * self.min_rtt?.as_secs_f64())
+    }
+}
+
+impl CongestionController for Bbr {
+    fn on_sample(&mut self, rtt: Duration, transmissions: u8, bytes: usize) {
+        self.tick_bytes += bytes;
+        self.tick_rtt = self.tick_rtt.max(rtt);
+        self.message_size = Some(
+            self.message_size
+                .map_or(bytes as f64, |size| bias(size, 0.125, bytes as f64)),
+        );
+
+        if transmissions == 0 {
+            self.min_rtt = Some(self.min_rtt.map_or(rtt, |min| min.min(rtt)));
+        }
+    }
+
+    fn on_ack(&mut self, _acked: usize, _pending: usize) {
+        if self.tick_bytes > 0 && !self.tick_rtt.is_zero() {
+            self.rates
+                .push_back(self.tick_bytes as f64 / self.tick_rtt.as_secs_f64());
+
+            while self.rates.len() > BW_WINDOW {
+                self.rates.pop_front();
+            }
+        }
+
+        self.tick_bytes = 0;
+        self.tick_rtt = Duration::ZERO;
+    }
+
+    // losses don't say much about the bandwidth, the delivery rate estimate already accounts for them
+    fn on_loss(&mut self, _lost: usize) {
+        self.tick_bytes = 0;
+        self.tick_rtt = Duration::ZERO;
+    }
+
+    fn window(&self) -> usize {
+        match (self.bdp(), self.message_size) {
+            (Some(bdp), Some(size)) if size > 0.0 => {
+                ((self.window_gain * bdp / size).ceil() as usize).max(1)
+            }
+            _ => self.initial_window,
+        }
+    }
+
+    fn pacing_interval(&self) -> Option<Duration> {
+        let rate = self.bandwidth()? * self.pacing_gain;
+        let size = self.message_size?;
+
+        Duration::try_from_secs_f64(size / rate).ok()
+    }
+
+    fn reset(&mut self) {
+        self.rates.clear();
+        self.min_rtt = None;
+        self.message_size = None;
+        self.tick_bytes = 0;
+        self.tick_rtt = Duration::ZERO;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A tick in which `count` messages of `bytes` were acked after `rtt`
+    fn tick(bbr: &mut Bbr, count: usize, bytes: usize, rtt: Duration) {
+        for _ in 0..count {
+            bbr.on_sample(rtt, 0, bytes);
+        }
+        bbr.on_ack(count, 0);
+    }
+
+    #[test]
+    fn sizes_the_window_by_the_bdp() {
+        let mut bbr = Bbr::new(4);
+        assert_eq!(bbr.window(), 4);
+        assert_eq!(bbr.pacing_interval(), None);
+
+        // 1000 bytes every 125ms
+        tick(&mut bbr, 10, 100, Duration::from_millis(125));
+
+        assert_eq!(bbr.bandwidth(), Some(8000.0));
+        assert_eq!(bbr.bdp(), Some(1000.0));
+        assert_eq!(bbr.window(), 20);
+        assert_eq!(bbr.pacing_interval(), Some(Duration::from_micros(12500)));
+    }
+
+    #[test]
+    fn keeps_the_best_rate_of_the_last_ticks() {
+        let mut bbr = Bbr::new(4);
+        tick(&mut bbr, 10, 100, Duration::from_millis(125));
+
+        for _ in 0..BW_WINDOW - 1 {
+            tick(&mut bbr, 1, 100, Duration::from_millis(125));
+        }
+        assert_eq!(bbr.bandwidth(), Some(8000.0));
+
+        tick(&mut bbr, 1, 100, Duration::from_millis(125));
+        assert_eq!(bbr.bandwidth(), Some(800.0));
+    }
+
+    #[test]
+    fn losses_leave_the_window_alone() {
+        let mut bbr = Bbr::new(4);
+        tick(&mut bbr, 10, 100, Duration::from_millis(125));
+
+        bbr.on_loss(5);
+        assert_eq!(bbr.window(), 20);
+
+        bbr.reset();
+        assert_eq!(bbr.window(), 4);
+    }
+}
@@ -0,0 +1,60 @@
+use std::time::{Duration, Instant};
+
+// A messages-per-second quota, as some gateways impose regardless of congestion,
+// see ChokerConfig::rate_limit
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RateLimit {
+    // Messages per second, must be positive
+    pub rate: f64,
+    // Messages that may go out back to back after a quiet period, at least one
+    pub burst: u32,
+}
+
+// Token bucket enforcing a RateLimit, every transmission takes one token
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    pub limit: RateLimit,
+    pub tokens: f64,
+    // When tokens was last topped up
+    pub at: Instant,
+}
+
+impl TokenBucket {
+    // Starts out full
+    pub fn new(limit: RateLimit, now: Instant) -> Self {
+        TokenBucket {
+            limit,
+            tokens: limit.burst as f64,
+            at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.at).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst as f64);
+        self.at = self.at.max(now);
+    }
+
+    // Takes a token if there is one
+    pub fn take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    // When the next token is there
+    pub fn next_token(&self) -> Instant {
+        let missing = (1.0 - self.tokens).max(0.0);
+
+        let wait = Duration::try_from_secs_f64(missing / self.limit.rate).unwrap_or(Duration::MAX);
+
+        crate::later(self.at, wait)
+    }
+}
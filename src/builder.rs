@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use crate::{Choker, ChokerConfig, ChokerError, CocoaWindow, Payload, RtoConfig, RTO};
+
+// Builds a Choker a setting at a time, checking the result once at build
+//
+// Anything without a setter of its own goes through config and rto_config
+#[derive(Debug, Clone, Copy)]
+pub struct ChokerBuilder {
+    pub config: ChokerConfig,
+    pub rto: RtoConfig,
+}
+
+impl ChokerBuilder {
+    pub fn new() -> Self {
+        ChokerBuilder {
+            config: ChokerConfig::default(),
+            rto: RtoConfig::default(),
+        }
+    }
+
+    // Starts from the given config, rather than the defaults
+    pub fn config(mut self, config: ChokerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn rto_config(mut self, rto: RtoConfig) -> Self {
+        self.rto = rto;
+        self
+    }
+
+    // RTO before any RTT was measured
+    pub fn initial_rto(mut self, initial_rto: Duration) -> Self {
+        self.rto.initial_rto = initial_rto;
+        self
+    }
+
+    pub fn min_rto(mut self, min_rto: Duration) -> Self {
+        self.rto.min_rto = min_rto;
+        self
+    }
+
+    pub fn max_rto(mut self, max_rto: Duration) -> Self {
+        self.rto.max_rto = max_rto;
+        self
+    }
+
+    pub fn max_window(mut self, max_window: usize) -> Self {
+        self.config.max_window = max_window;
+        self
+    }
+
+    pub fn nstart(mut self, nstart: usize) -> Self {
+        self.config.nstart = nstart;
+        self
+    }
+
+    pub fn max_retransmit(mut self, max_retransmit: u8) -> Self {
+        self.config.max_retransmit = max_retransmit;
+        self
+    }
+
+    pub fn exchange_lifetime(mut self, exchange_lifetime: Duration) -> Self {
+        self.config.exchange_lifetime = exchange_lifetime;
+        self
+    }
+
+    pub fn build(self) -> Result<Choker, ChokerError> {
+        self.build_for()
+    }
+
+    // Like build, for a Choker carrying another payload than Vec<u8>
+    pub fn build_for<T: Payload>(self) -> Result<Choker<RTO, CocoaWindow, T>, ChokerError> {
+        self.config.validate()?;
+        let rto = RTO::try_with_config(self.rto)?;
+
+        Ok(Choker::with_controller(
+            self.config,
+            rto,
+            CocoaWindow::new(self.config),
+        ))
+    }
+}
+
+impl Default for ChokerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,382 @@
+use std::time::{Duration, Instant};
+
+use crate::ChokerConfig;
+
+// Decides how many messages may be in flight, from what the Choker saw every tick
+//
+// The Choker only does the bookkeeping, every unicast tick it calls on_tick, then on_sample
+// for each sample of the tick, and then at most one of on_persistent_congestion on the tick
+// persistent congestion is declared, on_loss on ticks where messages timed out,
+// on_congestion on ticks the estimator hints at congestion, and on_ack on all others
+//
+// That last call is skipped on application-limited ticks, where the window had room
+// but nothing was queued to use it, and on every tick while the window is fixed
+// (ChokerConfig::fixed_window) or the peer is unreachable, multicast ticks don't call
+// the controller at all. Controllers that keep per-tick bookkeeping in on_ack, like Bbr
+// and Ledbat, have to expect on_tick and on_sample on ticks that end without any of the others
+pub trait CongestionController {
+    // Called every tick before any of the others, for controllers that go by time
+    fn on_tick(&mut self, _now: Instant) {}
+
+    // Every RTT sample of a tick, before on_ack, with the size of the message it came from,
+    // samples the estimator wouldn't trust (spurious, or from before a path change) are left out
+    fn on_sample(&mut self, _rtt: Duration, _transmissions: u8, _bytes: usize) {}
+
+    // A tick without timeouts, `acked` messages were acked since the last one,
+    // and `pending` ones are outstanding but still within their timeouts,
+    // not called on every such tick, see above
+    fn on_ack(&mut self, acked: usize, pending: usize);
+
+    // A tick in which `lost` messages timed out
+    fn on_loss(&mut self, lost: usize);
+
+    // Messages allowed in flight
+    fn window(&self) -> usize;
+
+    // Time to leave between transmissions, for controllers that pace rather than burst
+    fn pacing_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    // A hint of congestion that isn't a loss, like a rising RTT or a RST
+    fn on_congestion(&mut self) {}
+
+    // The peer answered with a RST, it's alive, the message just wasn't welcome
+    fn on_rst(&mut self) {}
+
+    // The network says the peer can't be reached (ICMP unreachable),
+    // nothing is sent until it can be again
+    fn on_unreachable(&mut self) {}
+
+    // Nothing was in flight or queued for `rtos` RTOs, what was learned about the window is stale
+    fn on_idle(&mut self, _rtos: u32) {}
+
+    // The last loss turned out to be a spurious timeout, undo what it did
+    fn on_spurious(&mut self) {}
+
+    // The path changed, forget everything learned about it
+    fn reset(&mut self) {}
+
+    // The session starts over, go back to how the controller started out,
+    // by default as on a path change
+    fn restart(&mut self) {
+        self.reset();
+    }
+
+    // Everything in flight kept timing out for several RTOs, see ChokerConfig::persistent_congestion,
+    // by default starts over as on a path change
+    fn on_persistent_congestion(&mut self) {
+        self.reset();
+    }
+}
+
+// How CocoaWindow shrinks on a tick with losses
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReductionPolicy {
+    // By ChokerConfig::decrease times the messages lost, CoCoA's own
+    PerLoss,
+    // To half, whatever was lost
+    Halve,
+    // Multiplied by the given factor, in (0, 1]
+    Multiply(f64),
+    // Back to one
+    Reset,
+}
+
+// How CocoaWindow grows by its factor on a clean tick while Rising
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Growth {
+    // By the factor, CoCoA's own
+    Additive,
+    // To 1 + factor times itself, for high-BDP links where additive growth
+    // takes dozens of RTOs to open the window, max_window still caps it
+    Multiplicative,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WindowState {
+    Rising {
+        factor: usize,
+        conseq: u8,
+    },
+    // Where a fresh CocoaWindow starts
+    #[default]
+    Halted,
+}
+
+// CoCoA's window, growing by an accelerating factor on clean ticks
+// and shrinking by the timeouts of a tick, see the ChokerConfig fields it takes
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CocoaWindow {
+    pub window_max: usize,
+    pub state: WindowState,
+    // window_max before the last reduction, restored when that turns out spurious
+    pub before_loss: Option<usize>,
+    // Slow start threshold, below it the window doubles every clean tick, see ChokerConfig::slow_start
+    pub ssthresh: usize,
+    // Clean ticks in a row while Halted, see ChokerConfig::rising_after
+    pub clean_ticks: u32,
+
+    pub config: ChokerConfig,
+}
+
+impl CocoaWindow {
+    pub fn new(config: ChokerConfig) -> Self {
+        CocoaWindow {
+            window_max: config.nstart.max(1),
+            state: WindowState::Halted,
+            before_loss: None,
+            ssthresh: config.initial_ssthresh,
+            clean_ticks: 0,
+            config,
+        }
+    }
+
+    fn cap(&mut self) {
+        self.window_max = self.window_max.min(self.config.max_window.max(1));
+
+        if self.config.strict_nstart {
+            self.window_max = self.window_max.min(self.config.nstart.max(1));
+        }
+    }
+}
+
+impl Default for CocoaWindow {
+    fn default() -> Self {
+        Self::new(ChokerConfig::default())
+    }
+}
+
+impl CongestionController for CocoaWindow {
+    fn on_ack(&mut self, _acked: usize, pending: usize) {
+        if pending > 0 {
+            // everything outstanding is still within its backed off timeout,
+            // this tick says nothing about the link
+            return;
+        }
+
+        self.before_loss = None;
+
+        if self.config.slow_start && self.window_max < self.ssthresh {
+            self.window_max = self.window_max.saturating_mul(2).min(self.ssthresh);
+        } else if let WindowState::Rising { factor, conseq } = &mut self.state {
+            if *factor == 0 {
+                // Start of the window accel
+                *factor += self.config.increase;
+            } else {
+                *conseq += 1;
+            }
+
+            if *conseq >= self.config.increase_after {
+                *factor += self.config.increase;
+                *conseq = 0;
+            }
+
+            self.window_max = match self.config.growth {
+                Growth::Additive => self.window_max.saturating_add(*factor),
+                Growth::Multiplicative => self.window_max.saturating_mul(*factor + 1),
+            };
+        } else {
+            self.clean_ticks = self.clean_ticks.saturating_add(1);
+
+            if self.clean_ticks >= self.config.rising_after {
+                self.state = WindowState::Rising {
+                    factor: 0,
+                    conseq: 0,
+                };
+                self.clean_ticks = 0;
+            }
+        }
+
+        self.cap();
+    }
+
+    fn on_loss(&mut self, lost: usize) {
+        self.state = WindowState::Halted;
+        self.clean_ticks = 0;
+        self.before_loss = Some(self.window_max);
+        self.ssthresh = (self.window_max / 2).max(1);
+
+        self.window_max = match self.config.reduction {
+            ReductionPolicy::PerLoss => {
+                let packet_min = (lost as f64 * self.config.decrease) as usize;
+                self.window_max.saturating_sub(packet_min)
+            }
+            ReductionPolicy::Halve => self.window_max / 2,
+            ReductionPolicy::Multiply(factor) => (self.window_max as f64 * factor) as usize,
+            ReductionPolicy::Reset => 1,
+        }
+        .max(1);
+    }
+
+    fn window(&self) -> usize {
+        self.window_max
+    }
+
+    // back off a little before it comes to losses
+    fn on_congestion(&mut self) {
+        self.state = WindowState::Halted;
+        self.clean_ticks = 0;
+        self.window_max = self.window_max.saturating_sub(1).max(1);
+        self.ssthresh = self.window_max;
+    }
+
+    // whatever was learned doesn't hold for the way back, start over from one
+    fn on_unreachable(&mut self) {
+        self.ssthresh = (self.window_max / 2).max(1);
+        self.window_max = 1;
+        self.state = WindowState::Halted;
+        self.clean_ticks = 0;
+    }
+
+    // Per RFC 7661, halve the window for every RTO spent idle,
+    // but keep ssthresh at 3/4 of it so slow start gets back there quickly
+    fn on_idle(&mut self, rtos: u32) {
+        self.ssthresh = self.ssthresh.max(self.window_max - self.window_max / 4);
+        self.window_max = self.window_max.checked_shr(rtos).unwrap_or(0).max(1);
+        self.state = WindowState::Halted;
+        self.clean_ticks = 0;
+    }
+
+    fn on_spurious(&mut self) {
+        if let Some(window_max) = self.before_loss.take() {
+            self.window_max = self.window_max.max(window_max);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.window_max = 1;
+        self.state = WindowState::Halted;
+        self.before_loss = None;
+        self.ssthresh = self.config.initial_ssthresh;
+        self.clean_ticks = 0;
+    }
+
+    // back at nstart, as new would have it
+    fn restart(&mut self) {
+        *self = Self::new(self.config);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(config: ChokerConfig) -> CocoaWindow {
+        CocoaWindow::new(ChokerConfig {
+            slow_start: false,
+            ..config
+        })
+    }
+
+    #[test]
+    fn spurious_loss_is_undone() {
+        let mut cocoa = window(ChokerConfig {
+            nstart: 8,
+            reduction: ReductionPolicy::Halve,
+            ..ChokerConfig::default()
+        });
+
+        cocoa.on_loss(1);
+        assert_eq!(cocoa.window(), 4);
+        assert_eq!(cocoa.state, WindowState::Halted);
+
+        cocoa.on_spurious();
+        assert_eq!(cocoa.window(), 8);
+
+        // only once
+        cocoa.on_loss(1);
+        cocoa.on_ack(1, 0);
+        cocoa.on_spurious();
+        assert_eq!(cocoa.window(), 4);
+    }
+
+    #[test]
+    fn grows_on_clean_ticks() {
+        let mut cocoa = window(ChokerConfig::default());
+        let start = cocoa.window();
+
+        for _ in 0..10 {
+            cocoa.on_ack(1, 0);
+        }
+        assert!(cocoa.window() > start);
+        assert!(matches!(cocoa.state, WindowState::Rising { .. }));
+    }
+
+    #[test]
+    fn holds_while_messages_are_pending() {
+        let mut cocoa = window(ChokerConfig::default());
+
+        for _ in 0..10 {
+            cocoa.on_ack(1, 1);
+        }
+        assert_eq!(cocoa.window(), 1);
+        assert_eq!(cocoa.state, WindowState::Halted);
+    }
+
+    #[test]
+    fn idle_halves_per_rto() {
+        let mut cocoa = window(ChokerConfig {
+            nstart: 16,
+            ..ChokerConfig::default()
+        });
+
+        cocoa.on_idle(2);
+        assert_eq!(cocoa.window(), 4);
+        assert_eq!(cocoa.ssthresh, cocoa.config.initial_ssthresh.max(12));
+
+        cocoa.on_idle(64);
+        assert_eq!(cocoa.window(), 1);
+    }
+
+    #[test]
+    fn idle_with_a_huge_window_does_not_panic() {
+        let mut cocoa = window(ChokerConfig {
+            max_window: usize::MAX,
+            ..ChokerConfig::default()
+        });
+        cocoa.window_max = usize::MAX;
+        cocoa.ssthresh = 1;
+
+        cocoa.on_idle(1);
+        assert_eq!(cocoa.window(), usize::MAX / 2);
+        assert_eq!(cocoa.ssthresh, usize::MAX - usize::MAX / 4);
+    }
+
+    #[test]
+    fn reductions_never_go_below_one() {
+        for reduction in [
+            ReductionPolicy::PerLoss,
+            ReductionPolicy::Halve,
+            ReductionPolicy::Multiply(0.0),
+            ReductionPolicy::Reset,
+        ] {
+            let mut cocoa = window(ChokerConfig {
+                nstart: 1,
+                reduction,
+                ..ChokerConfig::default()
+            });
+
+            cocoa.on_loss(100);
+            assert_eq!(cocoa.window(), 1, "{reduction:?}");
+        }
+    }
+
+    #[test]
+    fn reset_goes_to_one_and_restart_to_nstart() {
+        let mut cocoa = window(ChokerConfig {
+            nstart: 4,
+            ..ChokerConfig::default()
+        });
+
+        cocoa.reset();
+        assert_eq!(cocoa.window(), 1);
+
+        cocoa.restart();
+        assert_eq!(cocoa.window(), 4);
+    }
+}
@@ -0,0 +1,157 @@
+use std::time::Instant;
+
+use crate::CongestionController;
+
+// CUBIC scaling constant C, in messages per second cubed
+const C: f64 = 0.4;
+// Multiplicative decrease factor
+const BETA: f64 = 0.7;
+
+// CUBIC (RFC 8312) window growth
+//
+// Rather than growing by steps every clean tick, the window follows a cubic function of the time
+// since the last reduction, quickly climbing back to where the last loss happened,
+// carefully plateauing around it, and then probing beyond it faster and faster
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cubic {
+    // Current window, fractional so slow growth adds up
+    pub window: f64,
+    // Window right before the last reduction
+    pub w_max: f64,
+
+    // Seconds from the start of the epoch until the window is back at w_max
+    pub k: f64,
+    // Start of the current growth epoch, set on the first clean tick after a reduction
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub epoch_start: Option<Instant>,
+    // Time of the tick being handled
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub now: Option<Instant>,
+
+    pub c: f64,
+    pub beta: f64,
+}
+
+impl Cubic {
+    pub fn new(initial_window: usize) -> Self {
+        let window = initial_window.max(1) as f64;
+
+        Cubic {
+            window,
+            w_max: window,
+            k: 0.0,
+            epoch_start: None,
+            now: None,
+            c: C,
+            beta: BETA,
+        }
+    }
+
+    // The window `t` seconds into the epoch, W(t) = C * (t - K)^3 + W_max
+    fn target(&self, t: f64) -> f64 {
+        self.c * (t - self.k).powi(3) + self.w_max
+    }
+}
+
+impl CongestionController for Cubic {
+    fn on_tick(&mut self, now: Instant) {
+        self.now = Some(now);
+    }
+
+    fn on_ack(&mut self, _acked: usize, pending: usize) {
+        let Some(now) = self.now else {
+            return;
+        };
+
+        if pending > 0 {
+            // nothing new about the link, hold
+            return;
+        }
+
+        let start = *self.epoch_start.get_or_insert_with(|| {
+            // K = cbrt(W_max * (1 - beta) / C)
+            self.k = (self.w_max * (1.0 - self.beta) / self.c).cbrt();
+            now
+        });
+
+        let t = now.saturating_duration_since(start).as_secs_f64();
+        self.window = self.window.max(self.target(t));
+    }
+
+    fn on_loss(&mut self, _lost: usize) {
+        self.w_max = self.window;
+        self.window = (self.window * self.beta).max(1.0);
+        self.epoch_start = None;
+    }
+
+    fn window(&self) -> usize {
+        (self.window as usize).max(1)
+    }
+
+    fn on_congestion(&mut self) {
+        self.on_loss(1);
+    }
+
+    fn reset(&mut self) {
+        *self = Cubic {
+            c: self.c,
+            beta: self.beta,
+            ..Self::new(1)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn tick(cubic: &mut Cubic, now: Instant) -> usize {
+        cubic.on_tick(now);
+        cubic.on_ack(1, 0);
+        cubic.window()
+    }
+
+    #[test]
+    fn climbs_back_to_where_it_lost() {
+        let start = Instant::now();
+        let mut cubic = Cubic::new(10);
+
+        cubic.on_loss(1);
+        assert_eq!(cubic.window(), 7);
+        assert_eq!(cubic.w_max, 10.0);
+
+        // the epoch starts where the window was cut to
+        assert_eq!(tick(&mut cubic, start), 7);
+        assert!((cubic.k - 7.5f64.cbrt()).abs() < 1e-9);
+
+        let k = Duration::from_secs_f64(cubic.k);
+        assert_eq!(tick(&mut cubic, start + k / 2), 9);
+        assert_eq!(tick(&mut cubic, start + k), 10);
+        assert_eq!(tick(&mut cubic, start + k + Duration::from_secs(2)), 13);
+    }
+
+    #[test]
+    fn holds_while_messages_are_pending() {
+        let start = Instant::now();
+        let mut cubic = Cubic::new(10);
+        cubic.on_loss(1);
+
+        cubic.on_tick(start + Duration::from_secs(10));
+        cubic.on_ack(1, 1);
+        assert_eq!(cubic.window(), 7);
+        assert_eq!(cubic.epoch_start, None);
+    }
+
+    #[test]
+    fn reset_keeps_the_constants() {
+        let mut cubic = Cubic::new(10);
+        cubic.beta = 0.5;
+        cubic.reset();
+
+        assert_eq!(cubic.window(), 1);
+        assert_eq!(cubic.beta, 0.5);
+    }
+}
@@ -0,0 +1,120 @@
+use std::time::{Duration, Instant};
+
+use crate::ACK_TIMEOUT;
+
+// Estimates the RTO a Choker times its transmissions by
+pub trait RtoEstimator {
+    // Feeds the RTT sample of a message acked after `transmissions` retransmissions
+    fn on_ack(&mut self, sample: Duration, transmissions: u8);
+
+    // Called once every tick, with the samples of that tick
+    fn on_tick(&mut self, samples: &[(Duration, u8)], _now: Instant) {
+        for &(sample, transmissions) in samples {
+            self.on_ack(sample, transmissions);
+        }
+    }
+
+    // Called on every ACK taken in ahead of its tick, see Choker::ack_now,
+    // with the samples since the last tick so far, this one last,
+    // so the RTO can go by them right away while they're still weighed as one tick,
+    // on_tick gets them all again, by default they wait for it
+    fn on_early_samples(&mut self, _samples: &[(Duration, u8)]) {}
+
+    // Called on every retransmission
+    fn on_timeout(&mut self) {}
+
+    // Timeout for a message sent now
+    fn current(&self) -> Duration;
+
+    // Backoff factor for a message sent now
+    fn backoff_factor(&self) -> f64 {
+        2.0
+    }
+
+    // Timeout after the `transmissions`th retransmission of a message,
+    // given its timeout before that and the backoff factor it was sent with
+    fn next_timeout(&self, _transmissions: u8, current: Duration, factor: f64) -> Duration {
+        // a factor that makes no duration keeps the timeout where it was, rather than panicking
+        Duration::try_from_secs_f64(current.as_secs_f64() * factor).unwrap_or(current)
+    }
+
+    // Smoothed RTT, if the estimator keeps one
+    fn srtt(&self) -> Option<Duration> {
+        None
+    }
+
+    // Smoothed RTT variance, if the estimator keeps one
+    fn rttvar(&self) -> Option<Duration> {
+        None
+    }
+
+    // An upper layer reported heavy congestion without any timeouts, back the RTO off
+    fn on_congestion(&mut self) {}
+
+    // Forgets everything learned about the path, as if freshly created
+    fn reset(&mut self) {}
+
+    // Whether the RTT trend says the path is congesting, before any losses show it,
+    // the Choker stops growing its window and shrinks it by one while this is raised
+    fn congestion_hint(&self) -> bool {
+        false
+    }
+}
+
+// Plain RFC 7252, a fixed ACK_TIMEOUT with binary exponential backoff
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Basic {
+    pub ack_timeout: Duration,
+}
+
+impl Basic {
+    pub fn new() -> Self {
+        Basic {
+            ack_timeout: ACK_TIMEOUT,
+        }
+    }
+}
+
+impl Default for Basic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RtoEstimator for Basic {
+    fn on_ack(&mut self, _sample: Duration, _transmissions: u8) {}
+
+    fn current(&self) -> Duration {
+        self.ack_timeout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basic_ignores_samples_and_doubles() {
+        let mut basic = Basic::new();
+        basic.on_ack(Duration::from_millis(100), 0);
+        basic.on_tick(&[(Duration::from_secs(30), 1)], Instant::now());
+
+        assert_eq!(basic.current(), ACK_TIMEOUT);
+        assert_eq!(
+            basic.next_timeout(1, basic.current(), basic.backoff_factor()),
+            ACK_TIMEOUT * 2
+        );
+    }
+
+    #[test]
+    fn nonsense_factor_keeps_the_timeout() {
+        let basic = Basic::new();
+
+        assert_eq!(basic.next_timeout(1, ACK_TIMEOUT, f64::NAN), ACK_TIMEOUT);
+        assert_eq!(
+            basic.next_timeout(1, ACK_TIMEOUT, f64::INFINITY),
+            ACK_TIMEOUT
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::time::Duration;
+
+use crate::{
+    bias,
+    rto::{secs, ALPHA, BETA},
+    RtoEstimator,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FasorState {
+    // Clean samples, fast RTO with binary backoff
+    Normal,
+    // A timeout happened, the first retransmission waits the slow RTO
+    // in case the original is merely delayed, then falls back to the fast RTO
+    FastSlowFast,
+    // Losses persist, new exchanges start with the slow RTO
+    SlowFast,
+}
+
+// FASOR, Fast-Slow RTO
+//
+// The fast RTO is estimated per RFC 6298 from unambiguous samples,
+// the slow RTO from the time exchanges with retransmissions took to complete
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fasor {
+    pub srtt: Option<f64>,
+    pub rttvar: f64,
+
+    pub fast_rto: Duration,
+    pub slow_rto: Duration,
+
+    pub state: FasorState,
+
+    // What both RTOs start at, and go back to on reset
+    pub initial_rto: Duration,
+}
+
+impl Fasor {
+    pub fn new(initial_rto: Duration) -> Self {
+        Fasor {
+            srtt: None,
+            rttvar: 0.0,
+            fast_rto: initial_rto,
+            slow_rto: initial_rto,
+            state: FasorState::Normal,
+            initial_rto,
+        }
+    }
+}
+
+impl RtoEstimator for Fasor {
+    fn on_ack(&mut self, rtt: Duration, transmissions: u8) {
+        if transmissions == 0 {
+            let rtt = rtt.as_secs_f64();
+
+            if let Some(srtt) = &mut self.srtt {
+                self.rttvar = bias(self.rttvar, BETA, (*srtt - rtt).abs());
+                *srtt = bias(*srtt, ALPHA, rtt);
+            } else {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2.0;
+            }
+
+            let srtt = self.srtt.unwrap_or(rtt);
+            self.fast_rto =
+                Duration::try_from_secs_f64(srtt + 4.0 * self.rttvar).unwrap_or(self.fast_rto);
+            self.state = FasorState::Normal;
+        } else {
+            // rtt is from the first transmission, which covers the whole exchange
+            self.slow_rto = rtt.max(self.fast_rto.mul_f64(1.5));
+            self.state = FasorState::SlowFast;
+        }
+    }
+
+    fn on_timeout(&mut self) {
+        if self.state == FasorState::Normal {
+            self.state = FasorState::FastSlowFast;
+        }
+    }
+
+    fn current(&self) -> Duration {
+        if self.state == FasorState::SlowFast {
+            self.slow_rto
+        } else {
+            self.fast_rto
+        }
+    }
+
+    // FASOR schedules its retransmissions by state, rather than by a backoff factor
+    fn next_timeout(&self, transmissions: u8, current: Duration, _factor: f64) -> Duration {
+        match (self.state, transmissions) {
+            (FasorState::FastSlowFast, 1) => self.slow_rto,
+            (FasorState::FastSlowFast, 2) => self.fast_rto * 2,
+            (FasorState::SlowFast, 1) => self.fast_rto,
+            _ => current * 2,
+        }
+    }
+
+    fn srtt(&self) -> Option<Duration> {
+        self.srtt.map(secs)
+    }
+
+    fn rttvar(&self) -> Option<Duration> {
+        self.srtt.map(|_| secs(self.rttvar))
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.initial_rto);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn learned(rtt: Duration) -> Fasor {
+        let mut fasor = Fasor::new(Duration::from_secs(2));
+        fasor.on_ack(rtt, 0);
+        fasor
+    }
+
+    #[test]
+    fn first_timeout_waits_the_slow_rto_once() {
+        let mut fasor = learned(Duration::from_millis(100));
+        let fast = fasor.fast_rto;
+        assert_eq!(fast, Duration::from_millis(300));
+
+        fasor.on_timeout();
+        assert_eq!(fasor.state, FasorState::FastSlowFast);
+        assert_eq!(fasor.next_timeout(1, fast, 2.0), fasor.slow_rto);
+        assert_eq!(fasor.next_timeout(2, fasor.slow_rto, 2.0), fast * 2);
+    }
+
+    #[test]
+    fn retransmitted_exchanges_start_slow() {
+        let mut fasor = learned(Duration::from_millis(100));
+        fasor.on_ack(Duration::from_millis(200), 1);
+
+        // the exchange took less than the fast RTO, which still makes for a floor
+        assert_eq!(fasor.state, FasorState::SlowFast);
+        assert_eq!(fasor.current(), fasor.fast_rto.mul_f64(1.5));
+        assert_eq!(fasor.next_timeout(1, fasor.current(), 2.0), fasor.fast_rto);
+
+        // and a clean sample makes for fast starts again
+        fasor.on_ack(Duration::from_millis(100), 0);
+        assert_eq!(fasor.state, FasorState::Normal);
+        assert_eq!(fasor.current(), fasor.fast_rto);
+    }
+
+    #[test]
+    fn reset_goes_back_to_the_initial_rto() {
+        let mut fasor = learned(Duration::from_millis(100));
+        fasor.reset();
+
+        assert_eq!(fasor.srtt(), None);
+        assert_eq!(fasor.current(), Duration::from_secs(2));
+    }
+}
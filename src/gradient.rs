@@ -0,0 +1,144 @@
+use std::time::{Duration, Instant};
+
+use crate::{bias, RtoEstimator, RTO};
+
+// Delay-gradient (CDG style) congestion detection on top of another estimator
+//
+// Tracks how the lowest unambiguous RTT of every tick changes from tick to tick,
+// a persistently rising RTT means queues are building up somewhere on the path,
+// which is raised as a congestion_hint before it turns into losses.
+// Timeouts themselves are left to the inner estimator
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DelayGradient<E = RTO> {
+    pub inner: E,
+
+    // Smoothed change of the per-tick minimum RTT, in seconds per tick
+    pub gradient: f64,
+    // Minimum RTT of the last tick that had clean samples, in seconds
+    pub last_min: Option<f64>,
+
+    // EWMA gain of the gradient, in (0, 1]
+    pub gain: f64,
+    // Gradient per tick above which the hint is raised
+    pub threshold: Duration,
+}
+
+impl<E: RtoEstimator> DelayGradient<E> {
+    pub fn new(inner: E) -> Self {
+        DelayGradient {
+            inner,
+            gradient: 0.0,
+            last_min: None,
+            gain: 0.25,
+            threshold: Duration::from_millis(5),
+        }
+    }
+}
+
+impl<E: RtoEstimator> RtoEstimator for DelayGradient<E> {
+    fn on_ack(&mut self, sample: Duration, transmissions: u8) {
+        self.inner.on_ack(sample, transmissions);
+    }
+
+    fn on_tick(&mut self, samples: &[(Duration, u8)], now: Instant) {
+        let min = samples
+            .iter()
+            .filter(|(_, transmissions)| *transmissions == 0)
+            .map(|(rtt, _)| rtt.as_secs_f64())
+            .reduce(f64::min);
+
+        if let Some(min) = min {
+            if let Some(last) = self.last_min {
+                self.gradient = bias(self.gradient, self.gain, min - last);
+            }
+            self.last_min = Some(min);
+        }
+
+        self.inner.on_tick(samples, now);
+    }
+
+    // the gradient still goes by the minimum of the whole tick
+    fn on_early_samples(&mut self, samples: &[(Duration, u8)]) {
+        self.inner.on_early_samples(samples);
+    }
+
+    fn on_timeout(&mut self) {
+        self.inner.on_timeout();
+    }
+
+    fn current(&self) -> Duration {
+        self.inner.current()
+    }
+
+    fn backoff_factor(&self) -> f64 {
+        self.inner.backoff_factor()
+    }
+
+    fn next_timeout(&self, transmissions: u8, current: Duration, factor: f64) -> Duration {
+        self.inner.next_timeout(transmissions, current, factor)
+    }
+
+    fn srtt(&self) -> Option<Duration> {
+        self.inner.srtt()
+    }
+
+    fn rttvar(&self) -> Option<Duration> {
+        self.inner.rttvar()
+    }
+
+    fn on_congestion(&mut self) {
+        self.inner.on_congestion();
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+        self.gradient = 0.0;
+        self.last_min = None;
+    }
+
+    fn congestion_hint(&self) -> bool {
+        self.gradient > self.threshold.as_secs_f64() || self.inner.congestion_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Basic;
+
+    // DelayGradient after ticks with the given minimum RTTs, in milliseconds
+    fn after(mins: &[u64]) -> DelayGradient<Basic> {
+        let mut gradient = DelayGradient::new(Basic::new());
+        let now = Instant::now();
+
+        for &min in mins {
+            let min = Duration::from_millis(min);
+            gradient.on_tick(&[(min, 0), (min * 2, 0), (min / 2, 1)], now);
+        }
+
+        gradient
+    }
+
+    #[test]
+    fn rising_rtt_raises_the_hint() {
+        assert!(after(&[100, 120, 140, 160, 180]).congestion_hint());
+    }
+
+    #[test]
+    fn flat_rtt_does_not() {
+        let gradient = after(&[100, 101, 99, 100, 100]);
+
+        assert!(!gradient.congestion_hint());
+        assert_eq!(gradient.last_min, Some(0.1));
+    }
+
+    #[test]
+    fn reset_forgets_the_gradient() {
+        let mut gradient = after(&[100, 120, 140, 160, 180]);
+        gradient.reset();
+
+        assert!(!gradient.congestion_hint());
+        assert_eq!(gradient.last_min, None);
+    }
+}
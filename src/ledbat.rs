@@ -0,0 +1,189 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::CongestionController;
+
+// Target queuing delay from RFC 6817
+const TARGET: Duration = Duration::from_millis(100);
+// Minutes of base delay kept
+const BASE_HISTORY: usize = 10;
+const MINUTE: Duration = Duration::from_secs(60);
+
+// LEDBAT (RFC 6817) scavenger, for background traffic that yields to everything else
+//
+// Backs off as soon as the RTT rises above the lowest one seen, which means a queue is building up,
+// rather than waiting for losses, aiming for no more than `target` of queuing delay
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ledbat {
+    pub window: f64,
+
+    // Queuing delay aimed for
+    pub target: Duration,
+    // How fast the window moves towards the target
+    pub gain: f64,
+
+    // Lowest RTT of each of the last BASE_HISTORY minutes, oldest first
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub base_delays: VecDeque<(Instant, Duration)>,
+    // Lowest RTT of the tick being handled
+    pub current_delay: Option<Duration>,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub now: Option<Instant>,
+}
+
+impl Ledbat {
+    pub fn new() -> Self {
+        Ledbat {
+            window: 1.0,
+            target: TARGET,
+            gain: 1.0,
+            base_delays: VecDeque::new(),
+            current_delay: None,
+            now: None,
+        }
+    }
+
+    // Lowest RTT seen within the base history, the delay of the path without any queues
+    pub fn base_delay(&self) -> Option<Duration> {
+        self.base_delays.iter().map(|(_, delay)| *delay).min()
+    }
+
+    fn update_base_delay(&mut self, rtt: Duration, now: Instant) {
+        match self.base_delays.back_mut() {
+            Some((minute, delay)) if now.saturating_duration_since(*minute) < MINUTE => {
+                *delay = (*delay).min(rtt);
+            }
+            _ => {
+                self.base_delays.push_back((now, rtt));
+
+                while self.base_delays.len() > BASE_HISTORY {
+                    self.base_delays.pop_front();
+                }
+            }
+        }
+    }
+}
+
+impl Default for Ledbat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CongestionController for Ledbat {
+    fn on_tick(&mut self, now: Instant) {
+        self.now = Some(now);
+        self.current_delay = None;
+    }
+
+    fn on_sample(&mut self, rtt: Duration, transmissions: u8, _bytes: usize) {
+        // only unambiguous samples say anything about the delay
+        if transmissions > 0 {
+            return;
+        }
+
+        if let Some(now) = self.now {
+            self.update_base_delay(rtt, now);
+        }
+
+        self.current_delay = Some(self.current_delay.map_or(rtt, |delay| delay.min(rtt)));
+    }
+
+    fn on_ack(&mut self, acked: usize, _pending: usize) {
+        let (Some(current), Some(base)) = (self.current_delay, self.base_delay()) else {
+            return;
+        };
+
+        let target = self.target.as_secs_f64().max(f64::EPSILON);
+        let queuing = current.saturating_sub(base).as_secs_f64();
+        let off_target = (target - queuing) / target;
+
+        self.window += self.gain * off_target * acked as f64 / self.window;
+        self.window = self.window.max(1.0);
+    }
+
+    fn on_loss(&mut self, _lost: usize) {
+        self.window = (self.window / 2.0).max(1.0);
+    }
+
+    fn window(&self) -> usize {
+        (self.window as usize).max(1)
+    }
+
+    fn on_congestion(&mut self) {
+        self.on_loss(1);
+    }
+
+    fn reset(&mut self) {
+        self.window = 1.0;
+        self.base_delays.clear();
+        self.current_delay = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(ledbat: &mut Ledbat, rtt: Duration, now: Instant) {
+        ledbat.on_tick(now);
+        ledbat.on_sample(rtt, 0, 100);
+        ledbat.on_ack(1, 0);
+    }
+
+    #[test]
+    fn grows_without_queuing() {
+        let now = Instant::now();
+        let mut ledbat = Ledbat::new();
+
+        tick(&mut ledbat, Duration::from_millis(50), now);
+        assert_eq!(ledbat.base_delay(), Some(Duration::from_millis(50)));
+        assert_eq!(ledbat.window, 2.0);
+    }
+
+    #[test]
+    fn shrinks_above_target() {
+        let now = Instant::now();
+        let mut ledbat = Ledbat::new();
+        ledbat.window = 4.0;
+
+        tick(&mut ledbat, Duration::from_millis(50), now);
+        let window = ledbat.window;
+
+        // 200ms of queuing, twice the target
+        tick(&mut ledbat, Duration::from_millis(250), now);
+        assert!(ledbat.window < window);
+        assert_eq!(ledbat.base_delay(), Some(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn ignores_retransmitted_samples() {
+        let now = Instant::now();
+        let mut ledbat = Ledbat::new();
+
+        ledbat.on_tick(now);
+        ledbat.on_sample(Duration::from_millis(10), 1, 100);
+        ledbat.on_ack(1, 0);
+
+        assert_eq!(ledbat.base_delay(), None);
+        assert_eq!(ledbat.window, 1.0);
+    }
+
+    #[test]
+    fn keeps_one_base_delay_a_minute() {
+        let start = Instant::now();
+        let mut ledbat = Ledbat::new();
+
+        for minute in 0..BASE_HISTORY as u32 + 2 {
+            let rtt = Duration::from_millis(10 + minute as u64);
+            tick(&mut ledbat, rtt, start + MINUTE * minute);
+        }
+
+        assert_eq!(ledbat.base_delays.len(), BASE_HISTORY);
+        assert_eq!(ledbat.base_delay(), Some(Duration::from_millis(12)));
+    }
+}
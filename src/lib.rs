@@ -1,228 +1,3277 @@
 use std::{
-    collections::VecDeque,
+    cmp::Ordering,
+    collections::{HashMap, HashSet, VecDeque},
     time::{Duration, Instant},
 };
 
-pub struct Choker {
-    // Send buffer
-    pub buf: VecDeque<(usize, Vec<u8>)>,
+mod bbr;
+mod bucket;
+mod builder;
+mod controller;
+mod cubic;
+mod estimator;
+mod fasor;
+mod gradient;
+mod ledbat;
+mod observer;
+mod payload;
+mod recorder;
+mod rfc6298;
+mod rto;
 
-    // (MID, acked, retransmission counter, init/rtt, data)
-    pub window: Vec<(usize, u8, PacketState, Vec<u8>)>,
+pub use bbr::Bbr;
+pub use bucket::{RateLimit, TokenBucket};
+pub use builder::ChokerBuilder;
+pub use controller::{CocoaWindow, CongestionController, Growth, ReductionPolicy, WindowState};
+pub use cubic::Cubic;
+pub use estimator::{Basic, RtoEstimator};
+pub use fasor::{Fasor, FasorState};
+pub use gradient::DelayGradient;
+pub use ledbat::Ledbat;
+pub use observer::ChokerObserver;
+pub use payload::Payload;
+pub use recorder::{Record, Recorder};
+pub use rfc6298::Rfc6298;
+pub use rto::{
+    bias, AckWeighting, OutlierFilter, RtoConfig, RtoConfigError, RtoState, Variant, RTO,
+};
+
+pub struct Choker<E = RTO, C = CocoaWindow, T = Vec<u8>> {
+    // Send buffer, oldest first, what goes next is up to ChokerConfig::queue
+    buf: VecDeque<(u16, T)>,
+
+    // Send buffer for NON messages, oldest first
+    non_buf: VecDeque<(u16, T)>,
+    // Earliest time the next NON message may be released
+    non_next: Instant,
+    // NON messages enqueued so far, for promoting every nth one
+    non_count: u32,
+
+    window: Vec<Packet<T>>,
+
+    rto: E,
+    // Separate estimators for classes of messages, see enqueue_class,
+    // messages of classes not in here go by rto
+    classes: Vec<(u8, E)>,
+    // Classes of the messages in buf, by MID
+    buf_classes: HashMap<u16, u8>,
+    // Expiry of the messages in buf, by MID, see enqueue_with_expiry
+    buf_expiry: HashMap<u16, Instant>,
+    // Tokens of the messages in buf, by MID, see enqueue_with_token
+    buf_tokens: HashMap<u16, Vec<u8>>,
+    // Serials of the messages in buf and non_buf, by MID, see MessageHandle
+    buf_serials: HashMap<u16, u64>,
+    // Serial the next enqueued message gets
+    serial_next: u64,
+    rto_start: Instant,
+    rto_end: Instant,
+
+    // Decides how many messages may be in flight
+    controller: C,
+
+    // Enforces ChokerConfig::rate_limit on top of the window
+    bucket: Option<TokenBucket>,
+
+    dither: Option<Dither>,
+
+    // Messages that were given up on, see drain_failed
+    failed: Vec<FailedMessage<T>>,
+    // Messages that were acked and left the window, see drain_completed
+    completed: Vec<CompletedMessage<T>>,
+
+    // Where next_mid goes looking for a free MID
+    mid_next: u16,
+
+    // MIDs that left the window within EXCHANGE_LIFETIME, and when they did
+    recent: VecDeque<(u16, Instant)>,
+    // The MIDs in recent, to look them up without going through all of it
+    recent_mids: HashSet<u16>,
+    // How the messages with handles ended, by MID, forgotten along with recent
+    finished: HashMap<u16, (u64, MessageStatus)>,
+
+    // Since when nothing has been in flight or queued, see ChokerConfig::idle_validation
+    idle_since: Option<Instant>,
+
+    // No new messages go out anymore, see drain
+    draining: bool,
+
+    // New messages are held back until then, see pause_until
+    paused_until: Option<Instant>,
+
+    // The network said the peer can't be reached, see on_unreachable
+    unreachable: bool,
+
+    // MIDs handle_ack and handle_timeout want sent, see poll_transmit
+    transmit: Vec<u16>,
 
-    pub rto: RTO,
-    pub rto_start: Instant,
-    pub rto_end: Instant,
+    // MIDs ack_now took out of the window since the last tick
+    acked_since_tick: Vec<u16>,
+    // Their samples, by class, with their retransmissions and size, which wait for the tick
+    // like any other, so estimators and controllers still see one tick per tick
+    samples_since_tick: Vec<(Option<u8>, Duration, u8, usize)>,
 
-    pub window_max: usize,
-    pub window_state: WindowState,
+    // When persistent congestion was declared, see drain_persistent_congestion
+    persistent_congestion: Vec<Instant>,
+    // Persistent congestion was declared and nothing was heard since
+    congested: bool,
+
+    // Consecutive ticks that had timeouts and no ACKs
+    silent_ticks: u32,
+    // When probing an unresponsive peer, the earliest time the next message may be released
+    probing: Option<Instant>,
+
+    // Extremes of the unambiguous RTT samples seen so far
+    rtt_min: Option<Duration>,
+    rtt_max: Option<Duration>,
+
+    // Optional history of every tick, see set_recorder
+    recorder: Option<Recorder>,
+
+    // Told about every transmission, ACK and failure, see set_observer
+    observer: Option<Box<dyn ChokerObserver>>,
+
+    config: ChokerConfig,
 }
 
 impl Choker {
     pub fn new() -> Self {
+        Self::with_config(ChokerConfig::default())
+    }
+
+    pub fn with_config(config: ChokerConfig) -> Self {
+        Self::with_estimator(config, RTO::new())
+    }
+
+    // Like with_config, but rejects configs that don't validate
+    pub fn try_with_config(config: ChokerConfig) -> Result<Self, ChokerError> {
+        config.validate()?;
+
+        Ok(Self::with_config(config))
+    }
+
+    // Starts off an already seeded RTO, see RTO::from_history
+    pub fn with_rto(rto: RTO) -> Self {
+        Self::with_estimator(ChokerConfig::default(), rto)
+    }
+}
+
+impl Choker<Basic> {
+    // Plain RFC 7252 congestion control, for comparing against CoCoA
+    //
+    // A fixed ACK_TIMEOUT with binary exponential backoff, no RTT estimation,
+    // and a fixed window of NSTART
+    pub fn basic() -> Self {
+        let config = ChokerConfig {
+            fixed_window: true,
+            silent_backoff: false,
+            ..ChokerConfig::default()
+        };
+
+        Self::with_estimator(config, Basic::new())
+    }
+}
+
+impl<E: RtoEstimator> Choker<E> {
+    pub fn with_estimator(config: ChokerConfig, rto: E) -> Self {
+        Self::with_controller(config, rto, CocoaWindow::new(config))
+    }
+}
+
+impl<E: RtoEstimator, C: CongestionController, T: Payload> Choker<E, C, T> {
+    pub fn with_controller(config: ChokerConfig, rto: E, controller: C) -> Self {
+        let now = Instant::now();
+
         Self {
             buf: VecDeque::new(),
+            non_buf: VecDeque::new(),
+            non_next: now,
+            non_count: 0,
             window: Vec::new(),
-            rto: RTO::new(),
-            rto_start: Instant::now(),
-            rto_end: Instant::now().checked_add(Duration::from_secs(2)).unwrap(),
-            window_max: 1,
-            window_state: WindowState::Halted,
+            rto_start: now,
+            rto_end: later(now, rto.current()),
+            rto,
+            classes: Vec::new(),
+            buf_classes: HashMap::new(),
+            buf_expiry: HashMap::new(),
+            buf_tokens: HashMap::new(),
+            buf_serials: HashMap::new(),
+            serial_next: 1,
+            controller,
+            bucket: config.rate_limit.map(|limit| TokenBucket::new(limit, now)),
+            dither: None,
+            failed: Vec::new(),
+            completed: Vec::new(),
+            recent: VecDeque::new(),
+            recent_mids: HashSet::new(),
+            finished: HashMap::new(),
+            mid_next: 0,
+            idle_since: None,
+            draining: false,
+            paused_until: None,
+            unreachable: false,
+            transmit: Vec::new(),
+            acked_since_tick: Vec::new(),
+            samples_since_tick: Vec::new(),
+            persistent_congestion: Vec::new(),
+            congested: false,
+            silent_ticks: 0,
+            probing: None,
+            rtt_min: None,
+            rtt_max: None,
+            recorder: None,
+            observer: None,
+            config,
         }
     }
 
-    pub fn buf(&mut self) -> &mut VecDeque<(usize, Vec<u8>)> {
-        &mut self.buf
+    // The congestion state worth keeping across restarts or sleeps, see from_state
+    //
+    // Messages in flight and queued aren't part of it, those are up to the application
+    pub fn state(&self) -> ChokerState<&E, &C> {
+        ChokerState {
+            rto: &self.rto,
+            controller: &self.controller,
+            rtt_min: self.rtt_min,
+            rtt_max: self.rtt_max,
+            config: self.config,
+        }
     }
 
-    pub fn set_ack(&mut self, mid: usize, now: Instant) {
-        for (w_mid, _, p, _) in &mut self.window {
-            if *w_mid == mid {
-                if let &mut PacketState::Waiting(rtt) = p {
-                    *p = PacketState::Acked(rtt.duration_since(now))
-                }
-                break;
-            }
+    // Picks up where a Choker left off, with empty buffers and window
+    pub fn from_state(state: ChokerState<E, C>) -> Self {
+        let mut choker = Self::with_controller(state.config, state.rto, state.controller);
+
+        choker.rtt_min = state.rtt_min;
+        choker.rtt_max = state.rtt_max;
+
+        choker
+    }
+
+    // Randomize initial timeouts and tick intervals within [rto, rto * random_factor],
+    // so Chokers started together don't retransmit in lockstep
+    //
+    // random_factor has to be at least 1, ACK_RANDOM_FACTOR is the one RFC 7252 suggests
+    pub fn set_dither(
+        &mut self,
+        random_factor: f64,
+        rng: impl Random + 'static,
+    ) -> Result<(), ChokerError> {
+        if !(random_factor >= 1.0 && random_factor.is_finite()) {
+            return Err(ChokerError::OutOfRange("random_factor"));
         }
+
+        self.dither = Some(Dither {
+            random_factor,
+            rng: Box::new(rng),
+        });
+
+        Ok(())
     }
 
-    pub fn get_data<'a>(&'a self, mids: &'a [usize]) -> impl Iterator<Item = &[u8]> {
-        self.window.iter().filter_map(|e| {
-            if mids.contains(&e.0) {
-                Some(e.3.as_slice())
-            } else {
-                None
-            }
-        })
+    // Record the estimator and window after every tick, keeping the last `capacity` ticks
+    pub fn set_recorder(&mut self, capacity: usize) {
+        self.recorder = Some(Recorder::new(capacity));
     }
 
-    // Call this function at the deadline of every rto_end it gives back
+    // Has `observer` told about what happens to messages and the window from now on,
+    // replacing any earlier one
+    pub fn set_observer(&mut self, observer: impl ChokerObserver + 'static) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    // Takes the records made since the last call, oldest first, none without a recorder
+    pub fn drain_records(&mut self) -> impl Iterator<Item = Record> + '_ {
+        self.recorder.iter_mut().flat_map(Recorder::drain)
+    }
+
+    // Messages waiting for room in the window, oldest first, see enqueue
+    pub fn buf(&self) -> &VecDeque<(u16, T)> {
+        &self.buf
+    }
+
+    // NON messages waiting for poll_non, oldest first
+    pub fn non_buf(&self) -> &VecDeque<(u16, T)> {
+        &self.non_buf
+    }
+
+    // Messages in flight, and acked ones not yet pruned by a tick
+    pub fn window(&self) -> &[Packet<T>] {
+        &self.window
+    }
+
+    pub fn estimator(&self) -> &E {
+        &self.rto
+    }
+
+    pub fn estimator_mut(&mut self) -> &mut E {
+        &mut self.rto
+    }
+
+    pub fn controller(&self) -> &C {
+        &self.controller
+    }
+
+    pub fn controller_mut(&mut self) -> &mut C {
+        &mut self.controller
+    }
+
+    pub fn config(&self) -> &ChokerConfig {
+        &self.config
+    }
+
+    // When rto_tick wants to be called next
+    pub fn rto_end(&self) -> Instant {
+        self.rto_end
+    }
+
+    // When the Choker next wants attention, rto_end, or non_next while NON messages wait to go,
+    // for event loops to sleep until
+    pub fn next_deadline(&self) -> Instant {
+        if self.non_buf.is_empty() || self.unreachable || self.draining {
+            return self.rto_end;
+        }
+
+        // poll_non waits for a token and the end of a pause as well
+        let mut non_next = match &self.bucket {
+            Some(bucket) => self.non_next.max(bucket.next_token()),
+            None => self.non_next,
+        };
+        if let Some(until) = self.paused_until {
+            non_next = non_next.max(until);
+        }
+
+        self.rto_end.min(non_next)
+    }
+
+    // How long to sleep from `now` until next_deadline, zero if it already passed
+    pub fn time_until_tick(&self, now: Instant) -> Duration {
+        self.next_deadline().saturating_duration_since(now)
+    }
+
+    // Earliest time poll_non releases the next NON message
+    pub fn non_next(&self) -> Instant {
+        self.non_next
+    }
+
+    // Until when new messages are held back, see pause_until
+    pub fn paused_until(&self) -> Option<Instant> {
+        self.paused_until
+    }
+
+    // Whether the peer is considered unreachable, see on_unreachable
+    pub fn is_unreachable(&self) -> bool {
+        self.unreachable
+    }
+
+    // Whether the peer seems gone and is only probed, see ChokerConfig::probing_after
+    pub fn is_probing(&self) -> bool {
+        self.probing.is_some()
+    }
+
+    // Consecutive ticks that had timeouts and no ACKs
+    pub fn silent_ticks(&self) -> u32 {
+        self.silent_ticks
+    }
+
+    // Messages the Choker holds, queued and in the window alike
+    pub fn len(&self) -> usize {
+        self.pending_count() + self.window.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Messages sent and still waiting for their ACK
+    pub fn in_flight_count(&self) -> usize {
+        self.waiting().count()
+    }
+
+    // Messages queued and not yet sent, CON and NON alike
+    pub fn pending_count(&self) -> usize {
+        self.buf.len() + self.non_buf.len()
+    }
+
+    // Bytes sent and still waiting for their ACK
+    pub fn in_flight_bytes(&self) -> usize {
+        self.waiting().map(|p| p.data.size()).sum()
+    }
+
+    fn waiting(&self) -> impl Iterator<Item = &Packet<T>> {
+        self.window
+            .iter()
+            .filter(|p| matches!(p.state, PacketState::Waiting(_)))
+    }
+
+    // Queues a message for transmission,
+    // rejects MIDs that are queued, in flight, or completed within EXCHANGE_LIFETIME
     //
-    // Ticks at rto_end, prunes window and calculates new rto,
-    // retains unacked packets, fills window with new packets,
-    // sets and returns new rto_end, and the MIDs of the packets to be (re)transmitted
-    pub fn rto_tick(&mut self, now: Instant) -> (Instant, Vec<usize>) {
-        let acked: usize = self.window[..self.relevant_window_len()]
+    // The handle tells how the message is doing and cancels it, see status
+    pub fn enqueue(&mut self, mid: u16, data: T) -> Result<MessageHandle, EnqueueError> {
+        if self.mid_in_use(mid) {
+            return Err(EnqueueError::MidInUse);
+        }
+        self.check_capacity(&data)?;
+
+        Ok(self.push_queued(mid, data))
+    }
+
+    // Like enqueue, but hands the data back when it can't be queued,
+    // so a producer can hold on to it until there's room again, see capacity_hint
+    pub fn try_enqueue(&mut self, mid: u16, data: T) -> Result<MessageHandle, TryEnqueueError<T>> {
+        if self.mid_in_use(mid) {
+            return Err(TryEnqueueError::MidInUse(data));
+        }
+        if self.check_capacity(&data).is_err() {
+            return Err(TryEnqueueError::WouldBlock(data));
+        }
+
+        Ok(self.push_queued(mid, data))
+    }
+
+    fn push_queued(&mut self, mid: u16, data: T) -> MessageHandle {
+        self.buf.push_back((mid, data));
+
+        self.handle_queued(mid)
+    }
+
+    // Hands out the handle of a message that was just queued under `mid`
+    fn handle_queued(&mut self, mid: u16) -> MessageHandle {
+        let serial = self.serial_next;
+        self.serial_next += 1;
+
+        self.buf_serials.insert(mid, serial);
+        // whatever last went by the MID is forgotten
+        self.finished.remove(&mid);
+
+        MessageHandle { mid, serial }
+    }
+
+    // Where the message of `handle` is at, how it ended is remembered for EXCHANGE_LIFETIME
+    pub fn status(&self, handle: MessageHandle) -> MessageStatus {
+        if self.buf_serials.get(&handle.mid) == Some(&handle.serial) {
+            return MessageStatus::Queued;
+        }
+
+        match self
+            .window
             .iter()
-            .filter(|e| {
-                if let PacketState::Acked(_) = e.2 {
-                    true
-                } else {
-                    false
-                }
-            })
-            .count();
+            .find(|p| p.mid == handle.mid && p.serial == handle.serial)
+        {
+            Some(p) if p.cancelled => MessageStatus::Unknown,
+            Some(p) => match p.state {
+                PacketState::Waiting(_) => MessageStatus::InFlight,
+                PacketState::Acked(_) => MessageStatus::Acked,
+            },
+            None => match self.finished.get(&handle.mid) {
+                Some(&(serial, status)) if serial == handle.serial => status,
+                _ => MessageStatus::Unknown,
+            },
+        }
+    }
 
-        self.window.retain(|(_, transmissions, rtt, _)| {
-            if let &PacketState::Acked(rtt) = rtt {
-                self.rto.calc(*transmissions, rtt, acked);
+    // Like cancel, for the message of `handle`, so a later message reusing its MID is left alone
+    pub fn cancel_by_handle(&mut self, handle: MessageHandle) -> CancelResult<T> {
+        match self.status(handle) {
+            MessageStatus::Queued | MessageStatus::InFlight => self.cancel(handle.mid),
+            MessageStatus::Acked
+            | MessageStatus::Completed
+            | MessageStatus::Failed
+            | MessageStatus::Unknown => CancelResult::Unknown,
+        }
+    }
 
-                false
-            } else {
-                true
-            }
-        });
+    // Room left in buf and non_buf, for producers doing their own flow control
+    pub fn capacity_hint(&self) -> Capacity {
+        let queued = self.pending_count();
+        let bytes: usize = self
+            .buf
+            .iter()
+            .chain(&self.non_buf)
+            .map(|(_, d)| d.size())
+            .sum();
 
-        let mut unacked: usize = 0;
+        Capacity {
+            messages: self.config.max_queued.map(|max| max.saturating_sub(queued)),
+            bytes: self
+                .config
+                .max_queued_bytes
+                .map(|max| max.saturating_sub(bytes)),
+        }
+    }
 
-        let len = self.relevant_window_len();
-        for (_, t, _, _) in self.window[..len].iter_mut() {
-            *t += 1;
-            unacked += 1;
+    // Keeps a separate estimator for messages of `class`,
+    // for kinds of messages with RTTs of their own, like large blocks next to small telemetry
+    pub fn add_class(&mut self, class: u8, rto: E) {
+        if let Some((_, existing)) = self.classes.iter_mut().find(|(c, _)| *c == class) {
+            *existing = rto;
+        } else {
+            self.classes.push((class, rto));
         }
+    }
 
-        if unacked > 0 {
-            self.window_state = WindowState::Halted;
-            let packet_min = unacked / 2;
+    // Like enqueue, but times the message by the estimator of its class, see add_class
+    pub fn enqueue_class(
+        &mut self,
+        mid: u16,
+        data: T,
+        class: u8,
+    ) -> Result<MessageHandle, EnqueueError> {
+        let handle = self.enqueue(mid, data)?;
+        self.buf_classes.insert(mid, class);
 
-            if self.window_max > packet_min {
-                self.window_max -= packet_min;
-            } else {
-                self.window_max = 1;
-            }
+        Ok(handle)
+    }
+
+    // Like enqueue, but the message is dropped once `expires` passes, queued or in flight,
+    // and shows up in drain_failed as FailReason::Expired, it isn't taken as a loss
+    pub fn enqueue_with_expiry(
+        &mut self,
+        mid: u16,
+        data: T,
+        expires: Instant,
+    ) -> Result<MessageHandle, EnqueueError> {
+        let handle = self.enqueue(mid, data)?;
+        self.buf_expiry.insert(mid, expires);
+
+        Ok(handle)
+    }
+
+    // Like enqueue, but the message can also be acked by its CoAP token, see ack_by_token
+    pub fn enqueue_with_token(
+        &mut self,
+        mid: u16,
+        data: T,
+        token: Vec<u8>,
+    ) -> Result<MessageHandle, EnqueueError> {
+        let handle = self.enqueue(mid, data)?;
+        self.buf_tokens.insert(mid, token);
+
+        Ok(handle)
+    }
+
+    // RTO of a class, None if it has no estimator of its own
+    pub fn class_rto(&self, class: u8) -> Option<Duration> {
+        self.classes
+            .iter()
+            .find(|(c, _)| *c == class)
+            .map(|(_, rto)| rto.current())
+    }
+
+    // Queues a NON message, released at most once per RTO through poll_non
+    //
+    // Every `non_con_every`th message is promoted to a CON to keep the RTO measured,
+    // and goes through the window instead, the returned type tells which one it'll be sent as,
+    // the handle follows a NON message until poll_non releases it
+    pub fn enqueue_non(
+        &mut self,
+        mid: u16,
+        data: T,
+    ) -> Result<(MessageType, MessageHandle), EnqueueError> {
+        if self.mid_in_use(mid) {
+            return Err(EnqueueError::MidInUse);
+        }
+        self.check_capacity(&data)?;
+
+        self.non_count = self.non_count.wrapping_add(1);
+
+        if matches!(self.config.non_con_every, Some(n) if n > 0 && self.non_count.is_multiple_of(n))
+        {
+            Ok((MessageType::Confirmable, self.push_queued(mid, data)))
         } else {
-            if let WindowState::Rising { factor, conseq } = &mut self.window_state {
-                if *factor == 0 {
-                    // Start of the window accel
-                    *factor += 1;
-                } else {
-                    *conseq += 1;
-                }
+            self.non_buf.push_back((mid, data));
 
-                if *conseq == 3 {
-                    *factor += 1;
-                    *conseq = 0;
-                }
+            Ok((MessageType::NonConfirmable, self.handle_queued(mid)))
+        }
+    }
 
-                self.window_max += *factor;
-            } else {
-                self.window_state = WindowState::Rising {
-                    factor: 0,
-                    conseq: 0,
-                }
-            }
+    // Releases the next NON message, if the rate and rate limit allow it, check back at next_deadline
+    pub fn poll_non(&mut self, now: Instant) -> Option<(u16, T)> {
+        if now < self.non_next || self.paused(now) || self.unreachable || self.draining {
+            return None;
         }
 
-        while self.window_max > self.window.len() {
-            // fill the window with elements from the buffer
-            if let Some((mid, data)) = self.buf.pop_back() {
-                self.window.push((mid, 0, PacketState::Waiting(now), data))
-            } else {
-                break;
+        if let (Some(bucket), false) = (&mut self.bucket, self.non_buf.is_empty()) {
+            if !bucket.take(now) {
+                return None;
             }
         }
 
-        self.rto_start = now;
-        self.rto_end = self.rto_start + self.rto();
+        let message = self.non_buf.pop_front()?;
+        self.buf_serials.remove(&message.0);
+        self.non_next = later(now, self.rto());
+
+        Some(message)
+    }
+
+    // Hands out the next MID that isn't queued, in flight, or within its EXCHANGE_LIFETIME,
+    // counting up and wrapping around, None if every single one is taken
+    pub fn next_mid(&mut self) -> Option<u16> {
+        let start = self.mid_next;
 
-        let mids: Vec<_> = self.window[..self.relevant_window_len()]
+        // gathered once, rather than gone through for every MID tried
+        let queued: HashSet<u16> = self
+            .buf
             .iter()
-            .map(|e| e.0)
+            .chain(&self.non_buf)
+            .map(|(mid, _)| *mid)
+            .chain(self.window.iter().map(|p| p.mid))
             .collect();
 
-        (self.rto_end, mids)
+        for offset in 0..=u16::MAX {
+            let mid = start.wrapping_add(offset);
+
+            if !queued.contains(&mid) && !self.recent_mids.contains(&mid) {
+                self.mid_next = mid.wrapping_add(1);
+                return Some(mid);
+            }
+        }
+
+        None
     }
 
-    pub fn rto(&self) -> Duration {
-        self.rto.rto
+    // Where next_mid starts counting from, RFC 7252 suggests a random one
+    pub fn set_next_mid(&mut self, mid: u16) {
+        self.mid_next = mid;
     }
 
-    fn relevant_window_len(&self) -> usize {
-        usize::min(self.window_max, self.window.len())
+    // Rejects data that doesn't fit in buf and non_buf, see ChokerConfig::max_queued
+    fn check_capacity(&self, data: &T) -> Result<(), EnqueueError> {
+        let queued = self.pending_count();
+        if matches!(self.config.max_queued, Some(max) if queued >= max) {
+            return Err(EnqueueError::Full);
+        }
+
+        if let Some(max) = self.config.max_queued_bytes {
+            let bytes: usize = self
+                .buf
+                .iter()
+                .chain(&self.non_buf)
+                .map(|(_, d)| d.size())
+                .sum();
+
+            if bytes + data.size() > max {
+                return Err(EnqueueError::Full);
+            }
+        }
+
+        Ok(())
     }
-}
 
-const ALPHA: f64 = 0.125;
-const BETA: f64 = 0.25;
-const W_STRONG: f64 = 0.5;
-const W_WEAK: f64 = 0.25;
-pub struct RTO {
-    pub rto: Duration,
+    fn mid_in_use(&self, mid: u16) -> bool {
+        self.buf.iter().any(|(b_mid, _)| *b_mid == mid)
+            || self.non_buf.iter().any(|(b_mid, _)| *b_mid == mid)
+            || self.window.iter().any(|p| p.mid == mid)
+            || self.recent_mids.contains(&mid)
+    }
 
-    pub var_strong: f64,
-    pub strong: f64,
+    // Call this when the ACK for a message comes in
+    //
+    // The RTT is measured from the first transmission, as CoCoA's weak estimator expects,
+    // how many retransmissions it took goes along with the sample
+    //
+    // Returns the MIDs to retransmit right away, see ChokerConfig::fast_retransmit
+    pub fn ack(&mut self, mid: u16, now: Instant) -> Vec<u16> {
+        self.record_ack(mid, now, true).1
+    }
 
-    pub var_weak: f64,
-    pub weak: f64,
-}
+    // Like ack, for the message in flight that was enqueued with `token`,
+    // for layers that match responses by token, None if no message in flight has it
+    pub fn ack_by_token(&mut self, token: &[u8], now: Instant) -> Option<Vec<u16>> {
+        let mid = self.mid_of(token)?;
 
-impl RTO {
-    pub fn new() -> Self {
-        RTO {
-            rto: Duration::from_secs(2),
-            var_strong: 0.2,
-            strong: 2.0,
-            var_weak: 0.2,
-            weak: 2.0,
+        Some(self.ack(mid, now))
+    }
+
+    // The exchange of the message enqueued with `token` is done, without taking an RTT sample,
+    // for separate responses, which took the server however long it needed
+    //
+    // Returns the MID it was sent with, None if no message in flight has the token
+    pub fn complete_by_token(&mut self, token: &[u8], now: Instant) -> Option<u16> {
+        let mid = self.mid_of(token)?;
+
+        if let Some(p) = self.window.iter_mut().find(|p| p.mid == mid) {
+            p.stale = true;
+        }
+        self.record_ack(mid, now, false);
+
+        Some(mid)
+    }
+
+    fn mid_of(&self, token: &[u8]) -> Option<u16> {
+        self.window
+            .iter()
+            .find(|p| {
+                matches!(p.state, PacketState::Waiting(_)) && p.token.as_deref() == Some(token)
+            })
+            .map(|p| p.mid)
+    }
+
+    // Like ack, but does what the next tick would for this message right away,
+    // instead of leaving its slot in the window taken until then
+    //
+    // The message leaves the window, its sample goes to the estimator right away,
+    // and the window is refilled from buf, returns the MIDs to transmit now,
+    // fast retransmits included
+    //
+    // The sample is still weighed as part of the next tick, see RtoEstimator::on_early_samples,
+    // the controller only gets it with that tick
+    pub fn ack_now(&mut self, mid: u16, now: Instant) -> Vec<u16> {
+        let mut mids = self.ack(mid, now);
+        let window = self.window_max();
+
+        let Some(i) = self
+            .window
+            .iter()
+            .position(|p| p.mid == mid && matches!(p.state, PacketState::Acked(_)))
+        else {
+            return mids;
+        };
+
+        let p = self.window.remove(i);
+        if let PacketState::Acked(rtt) = p.state {
+            if !p.spurious && !p.stale {
+                self.samples_since_tick
+                    .push((p.class, rtt, p.transmissions, p.data.size()));
+
+                let early = self.early_samples(p.class);
+                estimator_mut(&mut self.rto, &mut self.classes, p.class).on_early_samples(&early);
+            }
         }
+
+        self.remember(p.mid, now);
+        self.acked_since_tick.push(p.mid);
+        self.complete(p);
+
+        // refilling can shrink it too, after an idle period
+        mids.extend(self.fill_window(now));
+        self.window_changed(window);
+
+        mids
+    }
+
+    // The samples since the last tick that go to the estimator of `class`, as unicast_tick sorts them
+    fn early_samples(&self, class: Option<u8>) -> Vec<(Duration, u8)> {
+        let known = |c: u8| self.classes.iter().any(|(known, _)| *known == c);
+        let own = |c: Option<u8>| match class.filter(|&class| known(class)) {
+            Some(class) => c == Some(class),
+            None => !c.is_some_and(known),
+        };
+
+        self.samples_since_tick
+            .iter()
+            .filter(|(c, ..)| own(*c))
+            .map(|&(_, rtt, transmissions, _)| (rtt, transmissions))
+            .collect()
     }
 
-    pub fn calc(&mut self, transmissions: u8, rtt: Duration, weighted_against: usize) {
-        let mut secs = self.rto.as_secs_f64();
-        let rtt = rtt.as_secs_f64();
-        let weight = weighted_against as f64;
+    // Releases queued messages right away if the window has room for them,
+    // rather than having them wait for the next tick, returns their MIDs
+    // along with whatever handle_ack and handle_timeout left to (re)transmit
+    //
+    // Call this after enqueueing and after every handle_*, multicast messages still
+    // wait for their interval, NON messages come from poll_non
+    pub fn poll_transmit(&mut self, now: Instant) -> Vec<u16> {
+        let mut mids: Vec<u16> = std::mem::take(&mut self.transmit)
+            .into_iter()
+            .filter(|mid| {
+                self.window.iter().any(|p| {
+                    p.mid == *mid && !p.cancelled && matches!(p.state, PacketState::Waiting(_))
+                })
+            })
+            .collect();
 
-        if transmissions == 0 {
-            // instant ACK
+        if !self.config.multicast {
+            let window = self.window_max();
 
-            // Update self.var_strong with a 4th of (self.strong - rtt)
-            self.var_strong = bias(self.var_strong, BETA / weight, self.strong - rtt);
+            mids.extend(self.fill_window(now));
+            self.window_changed(window);
+        }
 
-            // Bias self.strong with an 8th of rtt
-            self.strong = bias(self.strong, ALPHA / weight, rtt);
+        mids
+    }
 
-            // Update secs with a bias of (self.strong + 4*self.var_strong)
-            secs = bias(
-                secs,
-                W_STRONG / weight,
-                self.strong + (4.0 * self.var_strong),
-            );
-        } else if transmissions <= 2 {
-            // took 1 or 2 retransmissions
+    // When poll_transmit has nothing more, when to call handle_timeout next, see next_deadline
+    pub fn poll_timeout(&self) -> Instant {
+        self.next_deadline()
+    }
 
-            // Update self.weak with a 4th of (self.weak - rtt)
-            self.var_weak = bias(self.var_weak, BETA / weight, self.weak - rtt);
+    // Sans-IO counterpart of ack, what it wants retransmitted comes from poll_transmit
+    pub fn handle_ack(&mut self, mid: u16, now: Instant) -> AckResult {
+        let (result, mids) = self.record_ack(mid, now, true);
+        self.queue_transmit(mids);
 
-            self.weak = bias(self.weak, ALPHA / weight, rtt);
+        result
+    }
 
-            secs = bias(secs, W_WEAK / weight, self.weak + self.var_weak);
+    // Sans-IO counterpart of tick, does nothing before rto_end,
+    // what it wants (re)transmitted comes from poll_transmit, what completed or failed
+    // from drain_completed and drain_failed
+    pub fn handle_timeout(&mut self, now: Instant) {
+        if now < self.rto_end {
+            return;
         }
 
-        self.rto = Duration::from_secs_f64(secs);
+        let outcome = self.tick(now);
+        self.queue_transmit(outcome.transmit);
     }
-}
 
-// a gets `1 - weight` influence, b gets `weight` influence
-pub fn bias(a: f64, weight: f64, b: f64) -> f64 {
-    ((1.0 - weight) * a) + (b * weight)
-}
+    fn queue_transmit(&mut self, mids: Vec<u16>) {
+        for mid in mids {
+            if !self.transmit.contains(&mid) {
+                self.transmit.push(mid);
+            }
+        }
+    }
 
-pub enum WindowState {
-    Rising { factor: usize, conseq: u8 },
-    Halted,
-}
+    // Older name of ack, leaves fast retransmits to the timers
+    //
+    // Tells whether the MID was waiting for this ACK, so duplicates and strays can be counted
+    pub fn set_ack(&mut self, mid: u16, now: Instant) -> AckResult {
+        self.record_ack(mid, now, false).0
+    }
 
-pub enum PacketState {
-    Waiting(Instant),
-    Acked(Duration),
+    fn record_ack(
+        &mut self,
+        mid: u16,
+        now: Instant,
+        fast_retransmit: bool,
+    ) -> (AckResult, Vec<u16>) {
+        let window = self.window_max();
+        let mut spurious = false;
+        let mut acked_sent = None;
+        let mut result = if self.recent_mids.contains(&mid) {
+            AckResult::DuplicateAck
+        } else {
+            AckResult::UnknownMid
+        };
+
+        for p in &mut self.window {
+            if p.mid == mid {
+                result = AckResult::DuplicateAck;
+
+                if let PacketState::Waiting(sent) = p.state {
+                    result = AckResult::Acked;
+
+                    let rtt = now.saturating_duration_since(sent);
+                    p.state = PacketState::Acked(rtt);
+                    acked_sent = Some(p.last_sent);
+
+                    if let Some(observer) = &mut self.observer {
+                        observer.on_ack(p.mid, rtt, p.transmissions);
+                    }
+                    self.unreachable = false;
+
+                    if p.transmissions == 0 && !p.stale {
+                        self.rtt_min = Some(self.rtt_min.map_or(rtt, |min| min.min(rtt)));
+                        self.rtt_max = Some(self.rtt_max.map_or(rtt, |max| max.max(rtt)));
+                    }
+
+                    if let (true, 1, Some(srtt)) = (
+                        self.config.detect_spurious,
+                        p.transmissions,
+                        estimator(&self.rto, &self.classes, p.class).srtt(),
+                    ) {
+                        // An ACK this soon after the retransmission must be for the original,
+                        // the timeout fired too early
+                        let since = now.saturating_duration_since(p.last_sent);
+
+                        p.spurious = since < srtt / 2;
+                        spurious = p.spurious;
+                    }
+                }
+                break;
+            }
+        }
+
+        if spurious {
+            // undo the window reduction it caused
+            self.controller.on_spurious();
+        }
+
+        let mids = match (self.config.fast_retransmit, acked_sent) {
+            (Some(after), Some(acked_sent)) if fast_retransmit => {
+                self.fast_retransmit(acked_sent, after, now)
+            }
+            _ => Vec::new(),
+        };
+        self.window_changed(window);
+
+        (result, mids)
+    }
+
+    // Messages sent before one that just got acked count that ack against them,
+    // once `after` of those came in, they're taken as lost and retransmitted without waiting
+    fn fast_retransmit(&mut self, acked_sent: Instant, after: u8, now: Instant) -> Vec<u16> {
+        let mut mids = Vec::new();
+
+        let len = self.relevant_window_len();
+        for p in self.window[..len].iter_mut() {
+            if !matches!(p.state, PacketState::Waiting(_))
+                || p.cancelled
+                || p.last_sent >= acked_sent
+            {
+                continue;
+            }
+
+            p.later_acks = p.later_acks.saturating_add(1);
+            if p.later_acks < after || p.transmissions >= self.config.max_retransmit {
+                continue;
+            }
+
+            if let Some(bucket) = &mut self.bucket {
+                if !bucket.take(now) {
+                    // over the rate limit, the next ACK or its own timeout tries again
+                    continue;
+                }
+            }
+
+            let rto = estimator_mut(&mut self.rto, &mut self.classes, p.class);
+            retransmit(p, rto, self.config.max_backoff, now);
+
+            if let Some(observer) = &mut self.observer {
+                observer.on_retransmit(p.mid, p.transmissions, now);
+            }
+            mids.push(p.mid);
+        }
+
+        if !mids.is_empty() && !self.config.fixed_window {
+            self.controller.on_loss(mids.len());
+        }
+
+        mids
+    }
+
+    // Takes back a message, queued ones are removed and handed back,
+    // ones in flight aren't retransmitted anymore and leave the window at their timeout,
+    // or when acked, either way without their RTT being sampled or them being reported completed
+    pub fn cancel(&mut self, mid: u16) -> CancelResult<T> {
+        let queued = |buf: &VecDeque<(u16, T)>| buf.iter().position(|(b_mid, _)| *b_mid == mid);
+
+        let data = if let Some(i) = queued(&self.buf) {
+            self.buf.remove(i)
+        } else if let Some(i) = queued(&self.non_buf) {
+            self.non_buf.remove(i)
+        } else {
+            None
+        };
+
+        if let Some((_, data)) = data {
+            self.buf_classes.remove(&mid);
+            self.buf_expiry.remove(&mid);
+            self.buf_tokens.remove(&mid);
+            self.buf_serials.remove(&mid);
+
+            return CancelResult::Dequeued(data);
+        }
+
+        match self.window.iter_mut().find(|p| p.mid == mid) {
+            Some(p) if matches!(p.state, PacketState::Waiting(_)) => {
+                p.cancelled = true;
+                p.stale = true;
+
+                CancelResult::InFlight
+            }
+            _ => CancelResult::Unknown,
+        }
+    }
+
+    // Call this when the peer answered a message with a RST
+    //
+    // The exchange failed, it leaves the window right away without sampling its RTT,
+    // returns whether the MID was in the window
+    pub fn on_reset(&mut self, mid: u16, now: Instant) -> bool {
+        let Some(i) = self.window.iter().position(|p| p.mid == mid) else {
+            return false;
+        };
+
+        let p = self.window.remove(i);
+
+        self.remember(p.mid, now);
+        self.fail(FailedMessage {
+            mid: p.mid,
+            handle: p.handle(),
+            transmissions: p.transmissions,
+            reason: FailReason::Reset,
+            data: p.data,
+        });
+
+        // a sign of life, and only congestion when configured to be
+        let window = self.window_max();
+        self.unreachable = false;
+        if !self.config.fixed_window {
+            if self.config.reset_is_congestion {
+                self.controller.on_congestion();
+            } else {
+                self.controller.on_rst();
+            }
+        }
+        self.window_changed(window);
+
+        true
+    }
+
+    // Call this when the network reports the peer unreachable, like with an ICMP error
+    //
+    // Nothing is transmitted, not even retransmissions, until on_reachable is called
+    // or the peer is heard from, messages keep aging meanwhile and may give up
+    pub fn on_unreachable(&mut self) {
+        let window = self.window_max();
+        self.unreachable = true;
+
+        if !self.config.fixed_window {
+            self.controller.on_unreachable();
+        }
+        self.window_changed(window);
+    }
+
+    pub fn on_reachable(&mut self) {
+        self.unreachable = false;
+    }
+
+    // Stops releasing queued messages, CON, NON and multicast alike, for shutting down cleanly,
+    // what's in flight still completes or fails as usual, see drained
+    //
+    // Queued messages stay in buf and non_buf for the application to take care of, until reset
+    pub fn drain(&mut self) {
+        self.draining = true;
+    }
+
+    // Whether drain was called and nothing is in flight anymore
+    pub fn drained(&self) -> bool {
+        self.draining && self.window.is_empty()
+    }
+
+    // Holds back new messages until `until`, like when a 5.03 asks to come back after its Max-Age
+    //
+    // Messages already in flight carry on as usual, nothing else about the state changes
+    pub fn pause_until(&mut self, until: Instant) {
+        self.paused_until = Some(self.paused_until.map_or(until, |paused| paused.max(until)));
+    }
+
+    fn paused(&self, now: Instant) -> bool {
+        matches!(self.paused_until, Some(until) if now < until)
+    }
+
+    // Call this when something other than a loss says the path is congested,
+    // like a 5.03 or 4.29 response, or a filling queue in the modem
+    pub fn on_congestion_signal(&mut self, severity: Severity) {
+        let window = self.window_max();
+
+        match severity {
+            Severity::Light => {
+                if !self.config.fixed_window {
+                    self.controller.on_congestion();
+                }
+            }
+            Severity::Heavy => {
+                if !self.config.fixed_window {
+                    self.controller.on_loss(self.window_max());
+                }
+                self.rto.on_congestion();
+                for (_, rto) in &mut self.classes {
+                    rto.on_congestion();
+                }
+            }
+        }
+
+        self.window_changed(window);
+    }
+
+    // Call this when the local interface changed, and with it the path to the peer
+    //
+    // All RTT history is thrown away, the window starts over at one unless it's fixed,
+    // and messages already in flight won't be sampled since their RTTs span both paths
+    pub fn on_path_change(&mut self) {
+        let window = self.window_max();
+
+        self.rto.reset();
+        for (_, rto) in &mut self.classes {
+            rto.reset();
+        }
+
+        if !self.config.fixed_window {
+            self.controller.reset();
+        }
+        self.silent_ticks = 0;
+        self.probing = None;
+        self.rtt_min = None;
+        self.rtt_max = None;
+
+        for p in &mut self.window {
+            p.stale = true;
+        }
+        // and so are the samples ack_now took since the last tick
+        self.samples_since_tick.clear();
+
+        self.window_changed(window);
+    }
+
+    // Starts the session over, for when the DTLS session or network attachment is torn down
+    //
+    // Everything queued and in flight is dropped and handed back, in flight first,
+    // estimators and window start over as created, see CongestionController::restart,
+    // what's waiting to be drained stays
+    pub fn reset(&mut self, now: Instant) -> Vec<(u16, T)> {
+        let mut dropped: Vec<_> = self.window.drain(..).map(|p| (p.mid, p.data)).collect();
+        dropped.extend(self.buf.drain(..));
+        dropped.extend(self.non_buf.drain(..));
+
+        self.buf_classes.clear();
+        self.buf_expiry.clear();
+        self.buf_tokens.clear();
+        self.buf_serials.clear();
+        self.recent.clear();
+        self.recent_mids.clear();
+        self.finished.clear();
+        self.transmit.clear();
+        self.acked_since_tick.clear();
+        self.samples_since_tick.clear();
+
+        let window = self.window_max();
+
+        self.rto.reset();
+        for (_, rto) in &mut self.classes {
+            rto.reset();
+        }
+        self.controller.restart();
+
+        self.silent_ticks = 0;
+        self.probing = None;
+        self.rtt_min = None;
+        self.rtt_max = None;
+        self.window_changed(window);
+
+        self.non_next = now;
+        self.non_count = 0;
+        self.idle_since = None;
+        self.draining = false;
+        self.paused_until = None;
+        self.unreachable = false;
+        self.congested = false;
+        self.rto_start = now;
+        self.rto_end = later(now, self.rto.current());
+
+        dropped
+    }
+
+    // Transmission state of a message in the window
+    pub fn message_info(&self, mid: u16, now: Instant) -> Option<MessageInfo> {
+        let p = self.window.iter().find(|p| p.mid == mid)?;
+
+        Some(MessageInfo {
+            transmissions: p.transmissions,
+            age: now.saturating_duration_since(p.sent),
+            acked: matches!(p.state, PacketState::Acked(_)),
+        })
+    }
+
+    // The messages in the window, in flight or acked and not yet pruned, with how they're doing
+    pub fn in_flight(&self, now: Instant) -> impl Iterator<Item = InFlightInfo<'_>> + '_ {
+        self.window.iter().map(move |p| InFlightInfo {
+            mid: p.mid,
+            transmissions: p.transmissions,
+            elapsed: now.saturating_duration_since(p.sent),
+            acked: matches!(p.state, PacketState::Acked(_)),
+            size: p.data.size(),
+            token: p.token.as_deref(),
+        })
+    }
+
+    // Takes the messages that have been given up on since the last call
+    pub fn drain_failed(&mut self) -> impl Iterator<Item = FailedMessage<T>> + '_ {
+        self.failed.drain(..)
+    }
+
+    // Takes the messages that were acked and left the window since the last call,
+    // for matching them up with responses and logging their latency
+    pub fn drain_completed(&mut self) -> impl Iterator<Item = CompletedMessage<T>> + '_ {
+        self.completed.drain(..)
+    }
+
+    fn fail(&mut self, message: FailedMessage<T>) {
+        if let Some(observer) = &mut self.observer {
+            observer.on_give_up(message.mid, message.reason);
+        }
+        if let Some(handle) = message.handle {
+            self.finished
+                .insert(handle.mid, (handle.serial, MessageStatus::Failed));
+        }
+
+        self.failed.push(message);
+    }
+
+    // Tells the observer if window_max isn't `before` anymore
+    fn window_changed(&mut self, before: usize) {
+        let after = self.window_max();
+
+        if let (Some(observer), true) = (&mut self.observer, before != after) {
+            observer.on_window_change(before, after);
+        }
+    }
+
+    fn complete(&mut self, p: Packet<T>) {
+        // whoever cancelled it is done with it, an ACK that still came doesn't change that
+        if p.cancelled {
+            return;
+        }
+
+        let handle = p.handle();
+        if let Some(handle) = handle {
+            self.finished
+                .insert(handle.mid, (handle.serial, MessageStatus::Completed));
+        }
+
+        if let PacketState::Acked(rtt) = p.state {
+            self.completed.push(CompletedMessage {
+                mid: p.mid,
+                handle,
+                transmissions: p.transmissions,
+                rtt,
+                data: p.data,
+            });
+        }
+    }
+
+    // Takes the times persistent congestion was declared since the last call
+    pub fn drain_persistent_congestion(&mut self) -> impl Iterator<Item = Instant> + '_ {
+        self.persistent_congestion.drain(..)
+    }
+
+    pub fn get_data<'a>(&'a self, mids: &'a [u16]) -> impl Iterator<Item = &'a T> {
+        self.window.iter().filter_map(|p| {
+            if mids.contains(&p.mid) {
+                Some(&p.data)
+            } else {
+                None
+            }
+        })
+    }
+
+    // Call this function at the deadline of every rto_end it gives back
+    //
+    // Ticks at rto_end, prunes window and calculates new rto,
+    // retains unacked packets, fills window with new packets,
+    // retransmits packets whose (backed off) timeout has passed,
+    // sets and returns new rto_end, and the MIDs of the packets to be (re)transmitted
+    //
+    // While the peer stays silent, new packets and idle ticks use the backed off effective_rto
+    //
+    // Every packet keeps its own deadline, derived from the RTO when it was admitted,
+    // rto_end is the earliest of those, or one RTO from now if that's sooner
+    pub fn rto_tick(&mut self, now: Instant) -> (Instant, Vec<u16>) {
+        let outcome = self.tick(now);
+
+        (outcome.next, outcome.transmit)
+    }
+
+    // Like rto_tick, but tells everything that happened during the tick
+    pub fn tick(&mut self, now: Instant) -> TickOutcome {
+        let window = self.window_max();
+        let rto = self.rto();
+        let failed = self.failed.len();
+
+        let (next, transmit, completed) = if self.config.multicast {
+            let (next, mids) = self.multicast_tick(now);
+            (next, mids, Vec::new())
+        } else {
+            self.unicast_tick(now)
+        };
+
+        self.window_changed(window);
+
+        TickOutcome {
+            next,
+            transmit,
+            completed,
+            failed: self.failed[failed..]
+                .iter()
+                .map(|f| (f.mid, f.reason))
+                .collect(),
+            window: (window, self.window_max()),
+            rto: (rto, self.rto()),
+        }
+    }
+
+    // rto_tick, also giving back the MIDs that completed since the last tick
+    fn unicast_tick(&mut self, now: Instant) -> (Instant, Vec<u16>, Vec<u16>) {
+        self.controller.on_tick(now);
+
+        let mut samples = Vec::new();
+        // counting in what ack_now took care of since the last tick
+        let mut completed = std::mem::take(&mut self.acked_since_tick);
+        for (class, rtt, transmissions, size) in std::mem::take(&mut self.samples_since_tick) {
+            samples.push((class, rtt, transmissions));
+            self.controller.on_sample(rtt, transmissions, size);
+        }
+
+        let (acked, window): (Vec<_>, Vec<_>) = self
+            .window
+            .drain(..)
+            .partition(|p| matches!(p.state, PacketState::Acked(_)));
+        self.window = window;
+
+        for p in acked {
+            if let PacketState::Acked(rtt) = p.state {
+                // a spurious retransmission makes for a misleading weak sample
+                if !p.spurious && !p.stale {
+                    samples.push((p.class, rtt, p.transmissions));
+                    self.controller
+                        .on_sample(rtt, p.transmissions, p.data.size());
+                }
+            }
+            completed.push(p.mid);
+            self.remember(p.mid, now);
+            self.complete(p);
+        }
+
+        // every estimator ticks, with the samples of its own messages
+        let unclassed: Vec<_> = samples
+            .iter()
+            .filter(|(class, ..)| !self.classes.iter().any(|(c, _)| Some(*c) == *class))
+            .map(|&(_, rtt, transmissions)| (rtt, transmissions))
+            .collect();
+        self.rto.on_tick(&unclassed, now);
+
+        let acked = completed.len();
+        let heard = acked > 0;
+
+        for (class, rto) in &mut self.classes {
+            let own: Vec<_> = samples
+                .iter()
+                .filter(|(c, ..)| *c == Some(*class))
+                .map(|&(_, rtt, transmissions)| (rtt, transmissions))
+                .collect();
+            rto.on_tick(&own, now);
+        }
+
+        self.prune_recent(now);
+        self.expire(now);
+
+        // the messages that should go first get the slots of the window
+        match self.config.retransmit_order {
+            RetransmitOrder::Oldest => self.window.sort_by_key(|p| p.sent),
+            RetransmitOrder::FewestTransmissions => {
+                self.window.sort_by_key(|p| (p.transmissions, p.sent))
+            }
+            RetransmitOrder::Custom(cmp) => {
+                self.window.sort_by(|a, b| cmp(&a.header(), &b.header()))
+            }
+        }
+
+        let mut timed_out: usize = 0;
+        let mut pending: usize = 0;
+
+        let mut len = self.relevant_window_len();
+        let mut i = 0;
+        while i < len {
+            let p = &self.window[i];
+            if p.cancelled && p.deadline <= now {
+                // nobody is waiting for it anymore, and it wasn't lost either
+                let p = self.window.remove(i);
+                len -= 1;
+
+                self.remember(p.mid, now);
+            } else if let Some(reason) = self.give_up(&self.window[i], now) {
+                let p = self.window.remove(i);
+                len -= 1;
+                timed_out += 1;
+
+                self.remember(p.mid, now);
+                self.fail(FailedMessage {
+                    mid: p.mid,
+                    handle: p.handle(),
+                    transmissions: p.transmissions,
+                    reason,
+                    data: p.data,
+                });
+            } else {
+                let p = &self.window[i];
+                if p.deferred {
+                    // already counted when it first timed out
+                } else if p.deadline <= now {
+                    timed_out += 1;
+                } else {
+                    pending += 1;
+                }
+
+                i += 1;
+            }
+        }
+
+        if heard {
+            self.silent_ticks = 0;
+            self.probing = None;
+            self.congested = false;
+        } else if timed_out > 0 {
+            self.silent_ticks += 1;
+
+            if self.probing.is_none() && self.silent_ticks >= self.config.probing_after {
+                // peer seems gone, only probe it from now on
+                self.probing = Some(now);
+            }
+        }
+
+        let persistent = !heard && !self.congested && self.persistently_congested(now);
+        if persistent {
+            // whatever was learned about the path doesn't hold anymore, start over
+            self.congested = true;
+            self.persistent_congestion.push(now);
+
+            self.rto.reset();
+            for (_, rto) in &mut self.classes {
+                rto.reset();
+            }
+        }
+
+        if self.config.fixed_window || self.unreachable {
+            // the window stays at what the controller started with,
+            // or nothing gets through anyways
+        } else if persistent {
+            self.controller.on_persistent_congestion();
+        } else if timed_out > 0 {
+            self.controller.on_loss(timed_out);
+        } else if self.rto.congestion_hint() {
+            self.controller.on_congestion();
+        } else if self.app_limited() {
+            // the window wasn't what held anything back, so this tick didn't validate it
+        } else {
+            self.controller.on_ack(acked, pending);
+        }
+
+        let mut mids = self.retransmit_due(now);
+        mids.extend(self.fill_window(now));
+
+        self.rto_start = now;
+        self.rto_end = later(self.rto_start, self.dithered(self.effective_rto()));
+
+        // wake up in time for the first packet that's due
+        let len = self.relevant_window_len();
+        for p in self.window[..len].iter() {
+            // deferred ones wait for the next regular tick to keep within the budget,
+            // and nothing is due while unreachable
+            if !p.deferred && !self.unreachable {
+                self.rto_end = self.rto_end.min(p.deadline);
+            }
+        }
+
+        // queued messages only wake it up when they could actually go
+        let queued =
+            !self.buf.is_empty() && !self.unreachable && !self.draining && self.admits_next();
+        let admitting = queued && !self.paused(now);
+
+        if let Some(next) = self.probing {
+            if admitting {
+                self.rto_end = self.rto_end.min(next.max(now));
+            }
+        }
+
+        // and where the rate limit lets the next one go
+        if let Some(bucket) = &self.bucket {
+            let waiting = admitting || !self.unreachable && self.window.iter().any(|p| p.deferred);
+
+            if waiting {
+                self.rto_end = self.rto_end.min(bucket.next_token().max(now));
+            }
+        }
+
+        // pick up the queue where the pause ends
+        if let (Some(until), true) = (self.paused_until, queued) {
+            if until > now {
+                self.rto_end = self.rto_end.min(until);
+            }
+        }
+
+        let window_max = self.window_max();
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(Record {
+                at: now,
+                srtt: self.rto.srtt(),
+                rttvar: self.rto.rttvar(),
+                rto: self.rto.current(),
+                window_max,
+            });
+        }
+
+        (self.rto_end, mids, completed)
+    }
+
+    // Whether every message in flight was retransmitted at least
+    // PersistentCongestion::transmissions times, and even the newest of them was first sent
+    // PersistentCongestion::rtos RTOs ago
+    fn persistently_congested(&self, now: Instant) -> bool {
+        let Some(config) = self.config.persistent_congestion else {
+            return false;
+        };
+
+        let window = &self.window[..self.relevant_window_len()];
+        let Some(newest) = window.iter().map(|p| p.sent).max() else {
+            return false;
+        };
+
+        window
+            .iter()
+            .all(|p| p.transmissions >= config.transmissions)
+            && now.saturating_duration_since(newest) >= self.rto() * config.rtos
+    }
+
+    // Retransmits the messages in the window whose timeout passed, returns their MIDs
+    fn retransmit_due(&mut self, now: Instant) -> Vec<u16> {
+        let mut mids = Vec::new();
+
+        if self.unreachable {
+            return mids;
+        }
+
+        let mut spent = 0;
+
+        let len = self.relevant_window_len();
+        for p in self.window[..len].iter_mut() {
+            if p.deadline <= now && !p.cancelled {
+                let cost = match self.config.retransmit_budget {
+                    Some(RetransmitBudget::Messages(_)) => 1,
+                    Some(RetransmitBudget::Bytes(_)) => p.data.size(),
+                    None => 0,
+                };
+
+                // the first one always goes, so a message larger than the budget isn't stuck
+                if let Some(budget) = self.config.retransmit_budget {
+                    if !mids.is_empty() && spent + cost > budget.amount() {
+                        // over budget, it goes first on the next tick
+                        p.deferred = true;
+                        continue;
+                    }
+                }
+                spent += cost;
+
+                if let Some(bucket) = &mut self.bucket {
+                    if !bucket.take(now) {
+                        // over the rate limit, it goes once there's a token for it
+                        p.deferred = true;
+                        continue;
+                    }
+                }
+
+                let rto = estimator_mut(&mut self.rto, &mut self.classes, p.class);
+                retransmit(p, rto, self.config.max_backoff, now);
+
+                if let Some(observer) = &mut self.observer {
+                    observer.on_retransmit(p.mid, p.transmissions, now);
+                }
+                mids.push(p.mid);
+            }
+        }
+
+        mids
+    }
+
+    // Like rto_tick, but with a time to send each MID at, so they don't all go out back to back
+    //
+    // The MIDs are spread over ChokerConfig::pacing of the RTO,
+    // or go at the controller's pacing interval when it has one
+    pub fn rto_tick_paced(&mut self, now: Instant) -> (Instant, Vec<(Instant, u16)>) {
+        let (rto_end, mids) = self.rto_tick(now);
+
+        (rto_end, self.pace(mids, now))
+    }
+
+    fn pace(&self, mids: Vec<u16>, now: Instant) -> Vec<(Instant, u16)> {
+        let interval = match (self.controller.pacing_interval(), self.config.pacing) {
+            (Some(interval), _) => interval,
+            (None, Some(fraction)) if mids.len() > 1 => {
+                let spread = self.rto().as_secs_f64() * fraction;
+                Duration::try_from_secs_f64(spread / mids.len() as f64).unwrap_or_default()
+            }
+            _ => Duration::ZERO,
+        };
+
+        mids.into_iter()
+            .enumerate()
+            .map(|(i, mid)| (later(now, interval.saturating_mul(i as u32)), mid))
+            .collect()
+    }
+
+    pub fn rto(&self) -> Duration {
+        self.rto.current()
+    }
+
+    // Messages allowed in flight, what the controller allows up to ChokerConfig::max_window
+    pub fn window_max(&self) -> usize {
+        self.controller.window().min(self.config.max_window.max(1))
+    }
+
+    // Whether the window is pegged at ChokerConfig::max_window
+    pub fn at_max_window(&self) -> bool {
+        self.controller.window() >= self.config.max_window.max(1)
+    }
+
+    // How far apart the controller wants transmissions spaced, None to send them as they come
+    pub fn pacing_interval(&self) -> Option<Duration> {
+        self.controller.pacing_interval()
+    }
+
+    // The smoothed RTT itself, without the variance the RTO is padded with,
+    // for pacing, deadlines, or showing latency, None if the estimator doesn't keep one
+    pub fn srtt(&self) -> Option<Duration> {
+        self.rto.srtt()
+    }
+
+    // The RTO backed off once for every consecutive tick that timed out without any ACKs,
+    // what new messages and idle ticks wait for
+    //
+    // Goes back to the plain RTO as soon as an ACK comes in,
+    // so a dead peer isn't probed at the same cadence forever,
+    // always the plain RTO without ChokerConfig::silent_backoff
+    pub fn effective_rto(&self) -> Duration {
+        self.backed_off(self.rto(), self.rto.backoff_factor())
+    }
+
+    fn backed_off(&self, rto: Duration, factor: f64) -> Duration {
+        if !self.config.silent_backoff {
+            return rto;
+        }
+
+        let backoffs = self.silent_ticks.min(self.config.max_retransmit as u32);
+        let factor = factor.powi(backoffs as i32);
+
+        let backed_off = Duration::try_from_secs_f64(rto.as_secs_f64() * factor)
+            .unwrap_or(self.config.max_transmit_wait);
+
+        match self.config.max_backoff {
+            // never cut below the plain RTO
+            Some(max) => backed_off.min(max.max(rto)),
+            None => backed_off,
+        }
+    }
+
+    // Lowest RTT measured from a message that wasn't retransmitted
+    pub fn rtt_min(&self) -> Option<Duration> {
+        self.rtt_min
+    }
+
+    // Highest RTT measured from a message that wasn't retransmitted
+    pub fn rtt_max(&self) -> Option<Duration> {
+        self.rtt_max
+    }
+
+    // Multicast requests are never retransmitted or sampled,
+    // release one per multicast_interval, and only keep it around for get_data until the next tick
+    fn multicast_tick(&mut self, now: Instant) -> (Instant, Vec<u16>) {
+        for p in std::mem::take(&mut self.window) {
+            self.remember(p.mid, now);
+        }
+
+        self.prune_recent(now);
+
+        let mut mids = Vec::new();
+
+        if !self.paused(now) {
+            self.paused_until = None;
+        }
+
+        // nothing goes out while the peer can't be reached, paused, or once draining
+        let held = self.unreachable || self.draining || self.paused(now);
+        let limited = match &mut self.bucket {
+            Some(bucket) if !held && !self.buf.is_empty() => !bucket.take(now),
+            _ => false,
+        };
+
+        let queued = if held || limited {
+            None
+        } else {
+            self.pop_queued()
+        };
+
+        if let Some((mid, data)) = queued {
+            let serial = self.buf_serials.remove(&mid).unwrap_or(0);
+
+            self.window.push(Packet {
+                mid,
+                transmissions: 0,
+                state: PacketState::Waiting(now),
+                sent: now,
+                last_sent: now,
+                current_timeout: self.config.multicast_interval,
+                backoff: 1.0,
+                deadline: later(now, self.config.multicast_interval),
+                spurious: false,
+                later_acks: 0,
+                deferred: false,
+                stale: false,
+                cancelled: false,
+                class: None,
+                expires: None,
+                token: None,
+                serial,
+                data,
+            });
+
+            if let Some(observer) = &mut self.observer {
+                observer.on_transmit(mid, now);
+            }
+            mids.push(mid);
+        }
+
+        self.rto_start = now;
+        self.rto_end = later(
+            self.rto_start,
+            self.dithered(self.config.multicast_interval),
+        );
+
+        // pick up the queue where the pause ends
+        let queued = !self.buf.is_empty() && !self.unreachable && !self.draining;
+        if let (Some(until), true) = (self.paused_until, queued) {
+            if until > now {
+                self.rto_end = self.rto_end.min(until);
+            }
+        }
+
+        (self.rto_end, mids)
+    }
+
+    // Moves messages from buf into the window while it has room, returns their MIDs
+    fn fill_window(&mut self, now: Instant) -> Vec<u16> {
+        let mut mids = Vec::new();
+
+        if !self.paused(now) {
+            self.paused_until = None;
+        }
+        if self.unreachable || self.draining || self.paused_until.is_some() {
+            return mids;
+        }
+
+        if self.buf.is_empty() {
+            if self.window.is_empty() && self.idle_since.is_none() {
+                self.idle_since = Some(now);
+            }
+
+            return mids;
+        }
+
+        if let Some(since) = self.idle_since.take() {
+            let rto = self.rto();
+            if self.config.idle_validation && !self.config.fixed_window && !rto.is_zero() {
+                let idle = now.saturating_duration_since(since);
+                let rtos = (idle.as_secs_f64() / rto.as_secs_f64()) as u32;
+
+                if rtos > 0 {
+                    self.controller.on_idle(rtos);
+                }
+            }
+        }
+
+        while self.admits_next() {
+            if matches!(self.probing, Some(next) if now < next) {
+                break;
+            }
+
+            if let Some(bucket) = &mut self.bucket {
+                if !self.buf.is_empty() && !bucket.take(now) {
+                    break;
+                }
+            }
+
+            // fill the window with elements from the buffer
+            if let Some((mid, data)) = self.pop_queued() {
+                if self.probing.is_some() {
+                    let wait = data.size() as f64 / self.config.probing_rate;
+                    let wait = Duration::try_from_secs_f64(wait).unwrap_or(FAR);
+                    self.probing = Some(later(now, wait));
+                }
+
+                let class = self.buf_classes.remove(&mid);
+                let expires = self.buf_expiry.remove(&mid);
+                let token = self.buf_tokens.remove(&mid);
+                let serial = self.buf_serials.remove(&mid).unwrap_or(0);
+                let rto = estimator(&self.rto, &self.classes, class);
+                let backoff = rto.backoff_factor();
+                let timeout = self.backed_off(rto.current(), backoff);
+                let timeout = self.dithered(timeout);
+
+                self.window.push(Packet {
+                    mid,
+                    transmissions: 0,
+                    state: PacketState::Waiting(now),
+                    sent: now,
+                    last_sent: now,
+                    current_timeout: timeout,
+                    spurious: false,
+                    backoff,
+                    deadline: later(now, timeout),
+                    later_acks: 0,
+                    deferred: false,
+                    stale: false,
+                    cancelled: false,
+                    class,
+                    expires,
+                    token,
+                    serial,
+                    data,
+                });
+
+                if let Some(observer) = &mut self.observer {
+                    observer.on_transmit(mid, now);
+                }
+                mids.push(mid);
+            } else {
+                break;
+            }
+        }
+
+        mids
+    }
+
+    // Index in buf of the message that goes next, see ChokerConfig::queue
+    fn next_queued(&self) -> Option<usize> {
+        if self.buf.is_empty() {
+            return None;
+        }
+
+        match self.config.queue {
+            QueueDiscipline::Fifo => Some(0),
+            QueueDiscipline::Lifo => Some(self.buf.len() - 1),
+            QueueDiscipline::Priority => self
+                .buf
+                .iter()
+                .enumerate()
+                .min_by_key(|(i, (mid, _))| {
+                    let class = self.buf_classes.get(mid).map_or(u16::MAX, |&c| c as u16);
+                    (class, *i)
+                })
+                .map(|(i, _)| i),
+        }
+    }
+
+    fn pop_queued(&mut self) -> Option<(u16, T)> {
+        let i = self.next_queued()?;
+
+        self.buf.remove(i)
+    }
+
+    // Drops the messages whose expiry passed, from buf and window alike
+    fn expire(&mut self, now: Instant) {
+        if !self.buf_expiry.is_empty() {
+            let expired = |mid: &u16| matches!(self.buf_expiry.get(mid), Some(e) if *e <= now);
+
+            let (gone, kept) = self.buf.drain(..).partition(|(mid, _)| expired(mid));
+            self.buf = kept;
+
+            for (mid, data) in gone {
+                self.buf_expiry.remove(&mid);
+                self.buf_classes.remove(&mid);
+                self.buf_tokens.remove(&mid);
+                let serial = self.buf_serials.remove(&mid);
+                self.fail(FailedMessage {
+                    mid,
+                    handle: serial.map(|serial| MessageHandle { mid, serial }),
+                    transmissions: 0,
+                    reason: FailReason::Expired,
+                    data,
+                });
+            }
+        }
+
+        let mut i = 0;
+        while i < self.window.len() {
+            let p = &self.window[i];
+            if matches!(p.state, PacketState::Waiting(_))
+                && matches!(p.expires, Some(e) if e <= now)
+            {
+                let p = self.window.remove(i);
+
+                self.remember(p.mid, now);
+                self.fail(FailedMessage {
+                    mid: p.mid,
+                    handle: p.handle(),
+                    transmissions: p.transmissions,
+                    reason: FailReason::Expired,
+                    data: p.data,
+                });
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    // Keeps `mid` from being reused for EXCHANGE_LIFETIME after it left the window at `now`
+    fn remember(&mut self, mid: u16, now: Instant) {
+        self.recent.push_back((mid, now));
+        self.recent_mids.insert(mid);
+    }
+
+    fn prune_recent(&mut self, now: Instant) {
+        while let Some((_, done)) = self.recent.front() {
+            if now.saturating_duration_since(*done) < self.config.exchange_lifetime {
+                break;
+            }
+
+            if let Some((mid, _)) = self.recent.pop_front() {
+                self.recent_mids.remove(&mid);
+                self.finished.remove(&mid);
+            }
+        }
+    }
+
+    fn give_up(&self, p: &Packet<T>, now: Instant) -> Option<FailReason> {
+        let span = now.saturating_duration_since(p.sent);
+
+        if span >= self.config.max_transmit_wait {
+            Some(FailReason::MaxTransmitWait)
+        } else if p.deadline > now {
+            None
+        } else if p.transmissions >= self.config.max_retransmit {
+            Some(FailReason::MaxRetransmit)
+        } else if span > self.config.max_transmit_span {
+            Some(FailReason::MaxTransmitSpan)
+        } else {
+            None
+        }
+    }
+
+    // The messages at the front of the window that fit within window_max, the ones in flight
+    fn relevant_window_len(&self) -> usize {
+        let mut bytes = 0;
+
+        for (i, p) in self.window.iter().enumerate() {
+            bytes += p.data.size();
+
+            if !self.fits(i + 1, bytes) {
+                return i;
+            }
+        }
+
+        self.window.len()
+    }
+
+    // Whether the next message in buf would fit in the window, see ChokerConfig::window_accounting
+    fn admits_next(&self) -> bool {
+        let next = self.next_queued().map_or(0, |i| self.buf[i].1.size());
+        let bytes: usize = self.window.iter().map(|p| p.data.size()).sum();
+
+        self.fits(self.window.len() + 1, bytes + next)
+    }
+
+    // Whether the window has room left but nothing is queued to use it,
+    // growing it then would only give the next burst a window nothing ever tested
+    fn app_limited(&self) -> bool {
+        let bytes: usize = self.window.iter().map(|p| p.data.size()).sum();
+
+        self.buf.is_empty() && self.fits(self.window.len() + 1, bytes)
+    }
+
+    // Whether `count` messages of `bytes` in total fit in the window
+    fn fits(&self, count: usize, bytes: usize) -> bool {
+        match self.config.window_accounting {
+            WindowAccounting::Messages => count <= self.window_max(),
+            // a single message always goes, however large it is
+            WindowAccounting::Bytes(unit) => {
+                count == 1 || bytes <= self.window_max().saturating_mul(unit)
+            }
+            WindowAccounting::Both(max_bytes) => {
+                count <= self.window_max() && (count == 1 || bytes <= max_bytes)
+            }
+        }
+    }
+
+    fn dithered(&mut self, timeout: Duration) -> Duration {
+        if let Some(Dither { random_factor, rng }) = &mut self.dither {
+            let factor = 1.0 + (*random_factor - 1.0) * rng.random().clamp(0.0, 1.0);
+            Duration::try_from_secs_f64(timeout.as_secs_f64() * factor).unwrap_or(timeout)
+        } else {
+            timeout
+        }
+    }
+}
+
+// Backs a packet off for its next transmission, sent now
+fn retransmit<E: RtoEstimator, T>(
+    p: &mut Packet<T>,
+    rto: &mut E,
+    max_backoff: Option<Duration>,
+    now: Instant,
+) {
+    p.transmissions += 1;
+
+    rto.on_timeout();
+    p.current_timeout = rto.next_timeout(p.transmissions, p.current_timeout, p.backoff);
+    if let Some(max) = max_backoff {
+        p.current_timeout = p.current_timeout.min(max.max(rto.current()));
+    }
+
+    p.deadline = later(now, p.current_timeout);
+    p.last_sent = now;
+    p.later_acks = 0;
+    p.deferred = false;
+}
+
+// Furthest anything is scheduled ahead, so no Instant arithmetic overflows
+const FAR: Duration = Duration::from_secs(24 * 60 * 60);
+
+// now + after, but never further out than FAR
+fn later(now: Instant, after: Duration) -> Instant {
+    now.checked_add(after.min(FAR)).unwrap_or(now)
+}
+
+// The estimator of a class, or the default one
+fn estimator<'a, E>(rto: &'a E, classes: &'a [(u8, E)], class: Option<u8>) -> &'a E {
+    classes
+        .iter()
+        .find(|(c, _)| Some(*c) == class)
+        .map_or(rto, |(_, rto)| rto)
+}
+
+fn estimator_mut<'a, E>(
+    rto: &'a mut E,
+    classes: &'a mut [(u8, E)],
+    class: Option<u8>,
+) -> &'a mut E {
+    classes
+        .iter_mut()
+        .find(|(c, _)| Some(*c) == class)
+        .map_or(rto, |(_, rto)| rto)
+}
+
+impl Default for Choker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Counts and sizes rather than the messages themselves, payloads can be large
+impl<E: RtoEstimator, C: CongestionController, T: Payload> std::fmt::Debug for Choker<E, C, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Choker")
+            .field("queued", &self.buf.len())
+            .field("non_queued", &self.non_buf.len())
+            .field("in_flight", &self.in_flight_count())
+            .field("window", &self.window.len())
+            .field("window_max", &self.window_max())
+            .field("rto", &self.rto())
+            .field("srtt", &self.srtt())
+            .field("rto_end", &self.rto_end)
+            .field("silent_ticks", &self.silent_ticks)
+            .field("probing", &self.is_probing())
+            .field("unreachable", &self.unreachable)
+            .field("draining", &self.draining)
+            .field("paused_until", &self.paused_until)
+            .field("failed", &self.failed.len())
+            .field("completed", &self.completed.len())
+            .finish()
+    }
+}
+
+// Transmission parameters from RFC 7252
+pub const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+pub const MAX_RETRANSMIT: u8 = 4;
+pub const MAX_TRANSMIT_SPAN: Duration = Duration::from_secs(45);
+pub const MAX_TRANSMIT_WAIT: Duration = Duration::from_secs(93);
+pub const EXCHANGE_LIFETIME: Duration = Duration::from_secs(247);
+pub const NSTART: usize = 1;
+pub const DEFAULT_LEISURE: Duration = Duration::from_secs(5);
+// in bytes per second
+pub const PROBING_RATE: f64 = 1.0;
+
+// Leisure over which a group spreads its responses to a multicast request, per RFC 7252
+//
+// `response_size` is the estimated response size S in bytes, `group_size` the estimated group size G,
+// and `rate` the target data transfer rate R in bytes per second
+pub fn leisure(
+    response_size: usize,
+    group_size: usize,
+    rate: f64,
+) -> Result<Duration, ChokerError> {
+    let secs = response_size.saturating_mul(group_size) as f64 / rate;
+
+    Duration::try_from_secs_f64(secs).map_err(|_| ChokerError::OutOfRange("rate"))
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChokerConfig {
+    // Retransmissions of a message before giving up on it
+    pub max_retransmit: u8,
+    // Time after the first transmission in which a message may be retransmitted
+    pub max_transmit_span: Duration,
+    // Time after the first transmission after which a message is given up on
+    pub max_transmit_wait: Duration,
+    // Time after completion before a MID may be reused
+    pub exchange_lifetime: Duration,
+
+    // Promote every nth NON message to a CON
+    pub non_con_every: Option<u32>,
+
+    // Judge retransmissions acked within half an RTT as spurious,
+    // restoring the window and ignoring their RTT sample
+    pub detect_spurious: bool,
+
+    // Which messages get the window's slots when there are more in flight than it allows,
+    // and so which get retransmitted
+    pub retransmit_order: RetransmitOrder,
+
+    // Limit on the retransmissions of a single tick, for radios with duty-cycle limits,
+    // what doesn't fit goes out on the next tick
+    pub retransmit_budget: Option<RetransmitBudget>,
+
+    // Retransmit a message once this many messages sent after it were acked, without waiting
+    // for its timeout, the MIDs come back from Choker::ack
+    pub fast_retransmit: Option<u8>,
+
+    // Collapse the window and reset the RTO when everything in flight keeps timing out,
+    // rather than grinding on with ever more retransmissions
+    pub persistent_congestion: Option<PersistentCongestion>,
+
+    // Messages per second allowed out, new ones and retransmissions alike, NON and multicast too,
+    // whatever the window says
+    pub rate_limit: Option<RateLimit>,
+
+    // Most messages, and bytes of them, buf and non_buf hold together,
+    // enqueueing more fails with EnqueueError::Full, so a runaway producer can't exhaust memory
+    pub max_queued: Option<usize>,
+    pub max_queued_bytes: Option<usize>,
+
+    // Which queued message goes into the window next
+    pub queue: QueueDiscipline,
+
+    // Shrink the window by one on every RST
+    pub reset_is_congestion: bool,
+
+    // Outstanding messages allowed before any congestion feedback, the initial window_max
+    pub nstart: usize,
+    // Never allow more than this many messages in flight, whatever the controller says
+    pub max_window: usize,
+    // What window_max counts, messages or bytes
+    pub window_accounting: WindowAccounting,
+    // Never grow the window beyond nstart, as plain RFC 7252 would
+    pub strict_nstart: bool,
+    // Keep the window at nstart, ignoring congestion entirely
+    pub fixed_window: bool,
+
+    // Additive increase, the window grows by a factor every clean tick,
+    // which starts at `increase` and grows by it after every `increase_after` clean ticks in a row
+    pub increase: usize,
+    pub increase_after: u8,
+    // Whether the factor adds to the window or multiplies it
+    pub growth: Growth,
+    // Clean ticks in a row it takes for a halted window to start rising again,
+    // more than one keeps sparse losses from flipping it between the two every tick
+    pub rising_after: u32,
+
+    // How the window shrinks on losses
+    pub reduction: ReductionPolicy,
+    // For ReductionPolicy::PerLoss, the window shrinks by this times the messages
+    // that timed out in a tick, in [0, 1]
+    pub decrease: f64,
+
+    // Double the window every clean tick until it reaches ssthresh, before growing it
+    // additively as usual, ssthresh is halved window_max on every loss
+    pub slow_start: bool,
+    pub initial_ssthresh: usize,
+
+    // Start new messages and idle ticks at the RTO backed off for every silent tick,
+    // see Choker::effective_rto, off for plain RFC 7252 where every exchange starts at the RTO
+    pub silent_backoff: bool,
+
+    // Ceiling on backed off timeouts, so a link that comes back is noticed quickly,
+    // unlike RtoConfig::max_rto this doesn't bound the RTO itself
+    pub max_backoff: Option<Duration>,
+
+    // Shrink the window after nothing was sent for a few RTOs, as in RFC 7661,
+    // so the first burst after a quiet period doesn't go out at a stale window
+    pub idle_validation: bool,
+
+    // Fraction of the RTO rto_tick_paced spreads the MIDs of a tick over, in [0, 1]
+    pub pacing: Option<f64>,
+
+    // Silent ticks after which the peer is considered unresponsive
+    pub probing_after: u32,
+    // Bytes per second released to an unresponsive peer, must be positive
+    pub probing_rate: f64,
+
+    // Send to a multicast group, without retransmissions or RTT sampling
+    pub multicast: bool,
+    // Time between multicast requests, leaving room for the responses of the last one
+    pub multicast_interval: Duration,
+}
+
+impl Default for ChokerConfig {
+    fn default() -> Self {
+        ChokerConfig {
+            max_retransmit: MAX_RETRANSMIT,
+            max_transmit_span: MAX_TRANSMIT_SPAN,
+            max_transmit_wait: MAX_TRANSMIT_WAIT,
+            exchange_lifetime: EXCHANGE_LIFETIME,
+            non_con_every: Some(8),
+            detect_spurious: false,
+            retransmit_order: RetransmitOrder::Oldest,
+            retransmit_budget: None,
+            fast_retransmit: None,
+            persistent_congestion: None,
+            rate_limit: None,
+            queue: QueueDiscipline::Fifo,
+            max_queued: None,
+            max_queued_bytes: None,
+            reset_is_congestion: false,
+            nstart: NSTART,
+            max_window: 16,
+            window_accounting: WindowAccounting::Messages,
+            strict_nstart: false,
+            fixed_window: false,
+            increase: 1,
+            increase_after: 3,
+            growth: Growth::Additive,
+            rising_after: 1,
+            reduction: ReductionPolicy::PerLoss,
+            decrease: 0.5,
+            slow_start: false,
+            initial_ssthresh: usize::MAX,
+            silent_backoff: true,
+            max_backoff: None,
+            idle_validation: false,
+            pacing: None,
+            probing_after: 3,
+            probing_rate: PROBING_RATE,
+            multicast: false,
+            multicast_interval: DEFAULT_LEISURE,
+        }
+    }
+}
+
+impl ChokerConfig {
+    pub fn validate(&self) -> Result<(), ChokerConfigError> {
+        if self.increase == 0 {
+            Err(ChokerConfigError::OutOfRange("increase"))
+        } else if self.increase_after == 0 {
+            Err(ChokerConfigError::OutOfRange("increase_after"))
+        } else if !(0.0..=1.0).contains(&self.decrease) {
+            Err(ChokerConfigError::OutOfRange("decrease"))
+        } else if matches!(self.reduction, ReductionPolicy::Multiply(f) if !(f > 0.0 && f <= 1.0)) {
+            Err(ChokerConfigError::OutOfRange("reduction"))
+        } else if matches!(self.pacing, Some(p) if !(0.0..=1.0).contains(&p)) {
+            Err(ChokerConfigError::OutOfRange("pacing"))
+        } else if self.probing_rate.is_nan() || self.probing_rate <= 0.0 {
+            Err(ChokerConfigError::OutOfRange("probing_rate"))
+        } else if matches!(
+            self.window_accounting,
+            WindowAccounting::Bytes(0) | WindowAccounting::Both(0)
+        ) {
+            Err(ChokerConfigError::OutOfRange("window_accounting"))
+        } else if matches!(self.rate_limit, Some(l) if l.rate.is_nan() || l.rate <= 0.0 || l.burst == 0)
+        {
+            Err(ChokerConfigError::OutOfRange("rate_limit"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RetransmitOrder {
+    // First transmitted first, so head-of-line messages complete
+    Oldest,
+    // Least retransmitted first, then oldest
+    FewestTransmissions,
+    // Sorted by the given comparison of their headers, first ones first
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(fn(&Packet<()>, &Packet<()>) -> Ordering),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum QueueDiscipline {
+    // First enqueued first, so nothing starves
+    Fifo,
+    // Last enqueued first, for when only the freshest data matters
+    Lifo,
+    // Lowest class first, see Choker::enqueue_class, messages without one last,
+    // first enqueued first within a class
+    Priority,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WindowAccounting {
+    // window_max is the number of messages in flight
+    Messages,
+    // window_max is a byte budget, in units of the given bytes, against the data in flight,
+    // so a ping doesn't take as much of it as a block
+    Bytes(usize),
+    // window_max messages, but never more than the given bytes in flight all together,
+    // for peers short on both message processing and buffer space
+    Both(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RetransmitBudget {
+    Messages(usize),
+    Bytes(usize),
+}
+
+impl RetransmitBudget {
+    fn amount(self) -> usize {
+        match self {
+            RetransmitBudget::Messages(n) | RetransmitBudget::Bytes(n) => n,
+        }
+    }
+}
+
+// When the Choker declares persistent congestion, see ChokerConfig::persistent_congestion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PersistentCongestion {
+    // Retransmissions every message in flight has had
+    pub transmissions: u8,
+    // RTOs since the newest of them was first sent
+    pub rtos: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChokerConfigError {
+    // The named field is outside of its allowed range
+    OutOfRange(&'static str),
+}
+
+// Anything a Choker can fail at, rather than panicking
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChokerError {
+    Config(ChokerConfigError),
+    Rto(RtoConfigError),
+    // The named argument is outside of its allowed range
+    OutOfRange(&'static str),
+}
+
+impl From<ChokerConfigError> for ChokerError {
+    fn from(e: ChokerConfigError) -> Self {
+        ChokerError::Config(e)
+    }
+}
+
+impl From<RtoConfigError> for ChokerError {
+    fn from(e: RtoConfigError) -> Self {
+        ChokerError::Rto(e)
+    }
+}
+
+impl std::fmt::Display for ChokerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChokerError::Config(ChokerConfigError::OutOfRange(field)) => {
+                write!(f, "ChokerConfig::{field} is out of range")
+            }
+            ChokerError::Rto(RtoConfigError::OutOfRange(field)) => {
+                write!(f, "RtoConfig::{field} is out of range")
+            }
+            ChokerError::OutOfRange(arg) => write!(f, "{arg} is out of range"),
+        }
+    }
+}
+
+impl std::error::Error for ChokerError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    // Shrink the window a little
+    Light,
+    // As bad as losing the whole window, the RTO backs off too
+    Heavy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    Confirmable,
+    NonConfirmable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnqueueError {
+    // The MID is queued, in flight, or still within its EXCHANGE_LIFETIME
+    MidInUse,
+    // The queue is at ChokerConfig::max_queued or max_queued_bytes
+    Full,
+}
+
+// Like EnqueueError, with the data that wasn't queued, see Choker::try_enqueue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TryEnqueueError<T> {
+    MidInUse(T),
+    // Try again once messages left the queue
+    WouldBlock(T),
+}
+
+// Refers to a message enqueued on a Choker, see Choker::status and Choker::cancel_by_handle
+//
+// Stays tied to that one message even after its MID is reused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MessageHandle {
+    mid: u16,
+    serial: u64,
+}
+
+impl MessageHandle {
+    // MID the message is sent with
+    pub fn mid(&self) -> u16 {
+        self.mid
+    }
+}
+
+// Where a message is at, see Choker::status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageStatus {
+    // Waiting for room in the window
+    Queued,
+    // Sent and waiting for its ACK
+    InFlight,
+    // Acked, but not yet pruned from the window, see drain_completed
+    Acked,
+    // Acked and pruned, see drain_completed
+    Completed,
+    // Given up on, see drain_failed
+    Failed,
+    // Cancelled, a NON message poll_non released, done more than EXCHANGE_LIFETIME ago,
+    // or never enqueued on this Choker
+    Unknown,
+}
+
+// What Choker::set_ack made of an ACK
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AckResult {
+    // The message was waiting for it
+    Acked,
+    // The message was acked already, or left the window within EXCHANGE_LIFETIME
+    DuplicateAck,
+    // No message was sent with the MID
+    UnknownMid,
+}
+
+// What Choker::cancel did with a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CancelResult<T = Vec<u8>> {
+    // It was still queued, here's its data back
+    Dequeued(T),
+    // It was already sent, it won't be retransmitted
+    InFlight,
+    // It's neither queued nor waiting for an ACK
+    Unknown,
+}
+
+// Room left to enqueue, None where there's no limit, see Choker::capacity_hint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capacity {
+    pub messages: Option<usize>,
+    pub bytes: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageInfo {
+    // Retransmissions so far
+    pub transmissions: u8,
+    // Time since the first transmission
+    pub age: Duration,
+    // Acked, but not yet pruned from the window
+    pub acked: bool,
+}
+
+// A message in the window, see Choker::in_flight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InFlightInfo<'a> {
+    pub mid: u16,
+    // Retransmissions so far
+    pub transmissions: u8,
+    // Time since the first transmission
+    pub elapsed: Duration,
+    // Acked, but not yet pruned from the window
+    pub acked: bool,
+    // Size of its data
+    pub size: usize,
+    // CoAP token, see Choker::enqueue_with_token
+    pub token: Option<&'a [u8]>,
+}
+
+// What happened during a tick, see Choker::tick
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TickOutcome {
+    // When to tick next
+    pub next: Instant,
+    // MIDs to (re)transmit now
+    pub transmit: Vec<u16>,
+    // MIDs acked since the last tick
+    pub completed: Vec<u16>,
+    // MIDs given up on during the tick, and why, their data comes from drain_failed
+    pub failed: Vec<(u16, FailReason)>,
+    // window_max before and after the tick
+    pub window: (usize, usize),
+    // RTO before and after the tick
+    pub rto: (Duration, Duration),
+}
+
+// A message that was acked and removed from the window
+#[derive(Clone)]
+pub struct CompletedMessage<T = Vec<u8>> {
+    pub mid: u16,
+    // What enqueue handed out for it
+    pub handle: Option<MessageHandle>,
+    pub transmissions: u8,
+    // Time from the first transmission to the ACK, whether or not it was sampled
+    pub rtt: Duration,
+    pub data: T,
+}
+
+// A message that was removed from the window without being acked
+#[derive(Clone)]
+pub struct FailedMessage<T = Vec<u8>> {
+    pub mid: u16,
+    // What enqueue handed out for it
+    pub handle: Option<MessageHandle>,
+    pub transmissions: u8,
+    pub reason: FailReason,
+    pub data: T,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailReason {
+    // Timed out after the last allowed retransmission
+    MaxRetransmit,
+    // Timed out after MAX_TRANSMIT_SPAN passed, too late to retransmit
+    MaxTransmitSpan,
+    // Not acked within MAX_TRANSMIT_WAIT
+    MaxTransmitWait,
+    // The peer rejected it with a RST
+    Reset,
+    // Its expiry passed before it was acked
+    Expired,
+}
+
+// ACK_RANDOM_FACTOR from RFC 7252
+pub const ACK_RANDOM_FACTOR: f64 = 1.5;
+
+pub struct Dither {
+    pub random_factor: f64,
+    pub rng: Box<dyn Random>,
+}
+
+impl std::fmt::Debug for Dither {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Dither")
+            .field("random_factor", &self.random_factor)
+            .finish_non_exhaustive()
+    }
+}
+
+// A source of uniformly distributed numbers in [0, 1)
+pub trait Random {
+    fn random(&mut self) -> f64;
+}
+
+impl<F: FnMut() -> f64> Random for F {
+    fn random(&mut self) -> f64 {
+        self()
+    }
+}
+
+#[derive(Clone)]
+pub struct Packet<T = Vec<u8>> {
+    // CoAP Message ID, it wraps, so MIDs are only ever compared for equality, never ordered
+    pub mid: u16,
+
+    // retransmission counter
+    pub transmissions: u8,
+
+    pub state: PacketState,
+
+    // First transmission of this packet
+    pub sent: Instant,
+    // Latest (re)transmission of this packet
+    pub last_sent: Instant,
+
+    // Current timeout of this packet, starts at the RTO it was first sent with,
+    // and is multiplied by `backoff` on every retransmission
+    //
+    // This is never recomputed from the estimator, RTO updates only apply to packets
+    // admitted after them, so an exchange keeps backing off from where it started
+    pub current_timeout: Duration,
+    // Variable backoff factor, picked once from the RTO at first transmission
+    pub backoff: f64,
+    // When this packet is due for retransmission
+    pub deadline: Instant,
+
+    // Acked right after its first retransmission, see ChokerConfig::detect_spurious
+    pub spurious: bool,
+
+    // Acks for messages sent after this one's last transmission, see ChokerConfig::fast_retransmit
+    pub later_acks: u8,
+
+    // Due, but held back by ChokerConfig::retransmit_budget
+    pub deferred: bool,
+
+    // Sent before a path change, see Choker::on_path_change
+    pub stale: bool,
+
+    // Not to be retransmitted anymore, see Choker::cancel
+    pub cancelled: bool,
+
+    // Estimator class, see Choker::enqueue_class
+    pub class: Option<u8>,
+
+    // When the message stops being worth sending, see Choker::enqueue_with_expiry
+    pub expires: Option<Instant>,
+
+    // CoAP token, see Choker::enqueue_with_token
+    pub token: Option<Vec<u8>>,
+
+    // Tells apart messages that were sent with the same MID, see MessageHandle,
+    // 0 for ones that never got a handle
+    pub serial: u64,
+
+    pub data: T,
+}
+
+// The data is only given by its size, here and for the messages below
+impl<T: Payload> std::fmt::Debug for Packet<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Packet")
+            .field("mid", &self.mid)
+            .field("transmissions", &self.transmissions)
+            .field("state", &self.state)
+            .field("sent", &self.sent)
+            .field("last_sent", &self.last_sent)
+            .field("current_timeout", &self.current_timeout)
+            .field("deadline", &self.deadline)
+            .field("class", &self.class)
+            .field("expires", &self.expires)
+            .field("token", &self.token)
+            .field("cancelled", &self.cancelled)
+            .field("size", &self.data.size())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Payload> std::fmt::Debug for CompletedMessage<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompletedMessage")
+            .field("mid", &self.mid)
+            .field("handle", &self.handle)
+            .field("transmissions", &self.transmissions)
+            .field("rtt", &self.rtt)
+            .field("size", &self.data.size())
+            .finish()
+    }
+}
+
+impl<T: Payload> std::fmt::Debug for FailedMessage<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FailedMessage")
+            .field("mid", &self.mid)
+            .field("handle", &self.handle)
+            .field("transmissions", &self.transmissions)
+            .field("reason", &self.reason)
+            .field("size", &self.data.size())
+            .finish()
+    }
+}
+
+impl<T> Packet<T> {
+    // What enqueue handed out for this packet
+    pub fn handle(&self) -> Option<MessageHandle> {
+        (self.serial != 0).then_some(MessageHandle {
+            mid: self.mid,
+            serial: self.serial,
+        })
+    }
+
+    // Everything but the data
+    pub fn header(&self) -> Packet<()> {
+        Packet {
+            mid: self.mid,
+            transmissions: self.transmissions,
+            state: self.state,
+            sent: self.sent,
+            last_sent: self.last_sent,
+            current_timeout: self.current_timeout,
+            backoff: self.backoff,
+            deadline: self.deadline,
+            spurious: self.spurious,
+            later_acks: self.later_acks,
+            deferred: self.deferred,
+            stale: self.stale,
+            cancelled: self.cancelled,
+            class: self.class,
+            expires: self.expires,
+            token: self.token.clone(),
+            serial: self.serial,
+            data: (),
+        }
+    }
+}
+
+// Persistence-friendly view of a Choker, see Choker::state
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChokerState<E, C> {
+    pub rto: E,
+    pub controller: C,
+    pub rtt_min: Option<Duration>,
+    pub rtt_max: Option<Duration>,
+    pub config: ChokerConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketState {
+    Waiting(Instant),
+    Acked(Duration),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Events {
+        windows: Vec<(usize, usize)>,
+    }
+
+    struct Recording(Rc<RefCell<Events>>);
+
+    impl ChokerObserver for Recording {
+        fn on_window_change(&mut self, before: usize, after: usize) {
+            self.0.borrow_mut().windows.push((before, after));
+        }
+    }
+
+    #[test]
+    fn observer_sees_idle_shrink_on_poll_transmit() {
+        let mut choker: Choker = Choker::with_config(ChokerConfig {
+            idle_validation: true,
+            ..ChokerConfig::default()
+        });
+        let events = Rc::new(RefCell::new(Events::default()));
+        choker.set_observer(Recording(events.clone()));
+        choker.controller_mut().window_max = 8;
+
+        let start = Instant::now();
+        // nothing queued, the idle period starts here
+        assert!(choker.poll_transmit(start).is_empty());
+
+        choker.enqueue(1, vec![0]).unwrap();
+        let mids = choker.poll_transmit(later(start, choker.rto() * 10));
+
+        assert_eq!(mids, vec![1]);
+        assert_eq!(choker.window_max(), 1);
+        assert_eq!(events.borrow().windows, vec![(8, 1)]);
+    }
+
+    #[test]
+    fn handle_reports_completion_after_pruning() {
+        let mut choker = Choker::new();
+        let start = Instant::now();
+
+        let handle = choker.enqueue(1, vec![0]).unwrap();
+        assert_eq!(choker.status(handle), MessageStatus::Queued);
+
+        choker.poll_transmit(start);
+        assert_eq!(choker.status(handle), MessageStatus::InFlight);
+
+        choker.ack(1, later(start, Duration::from_millis(100)));
+        assert_eq!(choker.status(handle), MessageStatus::Acked);
+
+        choker.tick(later(start, Duration::from_millis(200)));
+        assert_eq!(choker.status(handle), MessageStatus::Completed);
+
+        let completed: Vec<_> = choker.drain_completed().collect();
+        assert_eq!(completed[0].handle, Some(handle));
+    }
+
+    #[test]
+    fn handle_reports_failure() {
+        let mut choker: Choker = Choker::with_config(ChokerConfig {
+            max_retransmit: 0,
+            ..ChokerConfig::default()
+        });
+        let start = Instant::now();
+
+        let handle = choker.enqueue(1, vec![0]).unwrap();
+        choker.poll_transmit(start);
+        choker.tick(later(start, choker.rto() * 2));
+
+        assert_eq!(choker.status(handle), MessageStatus::Failed);
+        let failed: Vec<_> = choker.drain_failed().collect();
+        assert_eq!(failed[0].handle, Some(handle));
+        assert_eq!(failed[0].reason, FailReason::MaxRetransmit);
+    }
+
+    #[test]
+    fn basic_starts_every_exchange_at_ack_timeout() {
+        let mut choker = Choker::basic();
+        let mut now = Instant::now();
+
+        choker.enqueue(1, vec![0]).unwrap();
+        choker.poll_transmit(now);
+
+        // the peer stays silent for a few ticks
+        for _ in 0..5 {
+            now = choker.rto_end();
+            choker.rto_tick(now);
+        }
+        assert!(choker.silent_ticks() > 0);
+
+        assert_eq!(choker.effective_rto(), ACK_TIMEOUT);
+    }
+
+    #[test]
+    fn ack_now_samples_count_right_away_and_as_one_tick() {
+        let mut choker: Choker = Choker::with_config(ChokerConfig {
+            nstart: 2,
+            ..ChokerConfig::default()
+        });
+        let start = Instant::now();
+        let rto = choker.rto();
+
+        choker.enqueue(1, vec![0]).unwrap();
+        choker.enqueue(2, vec![0]).unwrap();
+        choker.poll_transmit(start);
+
+        let at = later(start, Duration::from_millis(100));
+        choker.ack_now(1, at);
+        choker.ack_now(2, at);
+
+        // both samples count right away, weighed against each other
+        let mut early = RTO::new();
+        early.calc(0, Duration::from_millis(100), 2.0);
+        early.calc(0, Duration::from_millis(100), 2.0);
+        assert_ne!(choker.rto(), rto);
+        assert_eq!(choker.rto(), early.current());
+
+        choker.tick(later(at, Duration::from_millis(100)));
+
+        // and the tick takes them in once, as one tick
+        let mut single = RTO::new();
+        single.on_tick(
+            &[
+                (Duration::from_millis(100), 0),
+                (Duration::from_millis(100), 0),
+            ],
+            at,
+        );
+        assert_eq!(choker.rto(), single.current());
+        assert_eq!(choker.estimator().strong_age, 1);
+    }
+
+    #[test]
+    fn cancelled_messages_stay_cancelled_when_acked() {
+        let mut choker = Choker::new();
+        let start = Instant::now();
+        let handle = choker.enqueue(1, vec![0]).unwrap();
+        choker.poll_transmit(start);
+
+        assert_eq!(choker.cancel_by_handle(handle), CancelResult::InFlight);
+        assert_eq!(choker.set_ack(1, start), AckResult::Acked);
+        choker.tick(later(start, Duration::from_millis(100)));
+
+        assert!(choker.window().is_empty());
+        assert_eq!(choker.drain_completed().count(), 0);
+        assert_eq!(choker.status(handle), MessageStatus::Unknown);
+    }
+
+    #[test]
+    fn non_messages_get_handles() {
+        let mut choker: Choker = Choker::with_config(ChokerConfig {
+            non_con_every: Some(2),
+            ..ChokerConfig::default()
+        });
+        let start = Instant::now();
+
+        let (kind, non) = choker.enqueue_non(1, vec![1]).unwrap();
+        assert_eq!(kind, MessageType::NonConfirmable);
+        let (kind, con) = choker.enqueue_non(2, vec![2]).unwrap();
+        assert_eq!(kind, MessageType::Confirmable);
+
+        assert_eq!(choker.status(non), MessageStatus::Queued);
+        assert_eq!(choker.status(con), MessageStatus::Queued);
+
+        assert_eq!(choker.poll_non(start), Some((1, vec![1])));
+        assert_eq!(choker.status(non), MessageStatus::Unknown);
+
+        // the promoted one is followed like any other CON
+        assert_eq!(choker.poll_transmit(start), vec![2]);
+        assert_eq!(choker.status(con), MessageStatus::InFlight);
+        choker.set_ack(2, start);
+        choker.tick(later(start, Duration::from_millis(100)));
+        assert_eq!(choker.status(con), MessageStatus::Completed);
+        assert_eq!(
+            choker.drain_completed().next().and_then(|m| m.handle),
+            Some(con)
+        );
+
+        let (_, queued) = choker.enqueue_non(3, vec![3]).unwrap();
+        assert_eq!(
+            choker.cancel_by_handle(queued),
+            CancelResult::Dequeued(vec![3])
+        );
+        assert_eq!(choker.status(queued), MessageStatus::Unknown);
+    }
+
+    #[test]
+    fn next_mid_skips_mids_in_use() {
+        let mut choker = Choker::new();
+        let start = Instant::now();
+        choker.set_next_mid(u16::MAX);
+
+        // done, but within its EXCHANGE_LIFETIME
+        let done = choker.next_mid().unwrap();
+        assert_eq!(done, u16::MAX);
+        choker.enqueue(done, vec![0]).unwrap();
+        choker.poll_transmit(start);
+        choker.set_ack(done, start);
+        choker.tick(later(start, Duration::from_millis(100)));
+
+        // queued
+        choker.enqueue(0, vec![0]).unwrap();
+
+        choker.set_next_mid(u16::MAX);
+        assert_eq!(choker.next_mid(), Some(1));
+        assert_eq!(choker.next_mid(), Some(2));
+    }
+
+    // Sends `mids` at `now`, one per enqueue, on a Choker with room for all of them
+    fn sent(config: ChokerConfig, mids: &[u16], now: Instant) -> Choker {
+        let mut choker = Choker::with_config(ChokerConfig {
+            nstart: config.nstart.max(mids.len()),
+            ..config
+        });
+
+        for &mid in mids {
+            choker.enqueue(mid, vec![0; 10]).unwrap();
+        }
+        assert_eq!(choker.poll_transmit(now), mids);
+
+        choker
+    }
+
+    #[test]
+    fn retransmits_with_variable_backoff() {
+        let start = Instant::now();
+        let mut choker = sent(ChokerConfig::default(), &[1], start);
+
+        // a 2s RTO is medium, backing off by 2
+        let first = choker.window()[0].deadline;
+        assert_eq!(first, later(start, ACK_TIMEOUT));
+
+        let (_, mids) = choker.rto_tick(first);
+        assert_eq!(mids, vec![1]);
+        assert_eq!(choker.window()[0].transmissions, 1);
+        assert_eq!(choker.window()[0].current_timeout, ACK_TIMEOUT * 2);
+        assert_eq!(choker.window()[0].deadline, later(first, ACK_TIMEOUT * 2));
+    }
+
+    #[test]
+    fn small_rtos_back_off_by_three() {
+        let start = Instant::now();
+        let rto = RTO::from_history(Duration::from_millis(100), Duration::from_millis(10));
+        let timeout = rto.current();
+
+        let mut choker = Choker::with_rto(rto);
+        choker.enqueue(1, vec![0]).unwrap();
+        choker.poll_transmit(start);
+
+        let (_, mids) = choker.rto_tick(later(start, timeout));
+        assert_eq!(mids, vec![1]);
+        assert_eq!(choker.window()[0].current_timeout, timeout * 3);
+    }
+
+    fn failures(choker: &mut Choker) -> Vec<(u16, FailReason)> {
+        choker.drain_failed().map(|f| (f.mid, f.reason)).collect()
+    }
+
+    #[test]
+    fn gives_up_after_max_retransmit() {
+        let mut now = Instant::now();
+        let mut choker = sent(ChokerConfig::default(), &[1], now);
+
+        for _ in 0..MAX_RETRANSMIT {
+            now = choker.window()[0].deadline;
+            assert_eq!(choker.rto_tick(now).1, vec![1]);
+        }
+
+        now = choker.window()[0].deadline;
+        choker.rto_tick(now);
+        assert!(choker.window().is_empty());
+        assert_eq!(failures(&mut choker), vec![(1, FailReason::MaxRetransmit)]);
+    }
+
+    #[test]
+    fn gives_up_after_max_transmit_span() {
+        let start = Instant::now();
+        let mut choker = sent(
+            ChokerConfig {
+                max_transmit_span: Duration::from_secs(1),
+                ..ChokerConfig::default()
+            },
+            &[1],
+            start,
+        );
+
+        // due past the span, too late to retransmit
+        choker.rto_tick(later(start, ACK_TIMEOUT));
+        assert_eq!(
+            failures(&mut choker),
+            vec![(1, FailReason::MaxTransmitSpan)]
+        );
+    }
+
+    #[test]
+    fn gives_up_after_max_transmit_wait() {
+        let start = Instant::now();
+        let mut choker = sent(
+            ChokerConfig {
+                max_transmit_wait: Duration::from_secs(1),
+                ..ChokerConfig::default()
+            },
+            &[1],
+            start,
+        );
+
+        // not even due yet
+        choker.rto_tick(later(start, Duration::from_secs(1)));
+        assert_eq!(
+            failures(&mut choker),
+            vec![(1, FailReason::MaxTransmitWait)]
+        );
+    }
+
+    #[test]
+    fn every_message_keeps_its_own_deadline() {
+        let start = Instant::now();
+        let mut choker = sent(ChokerConfig::default(), &[1], start);
+
+        choker.enqueue(2, vec![0]).unwrap();
+        choker.controller_mut().window_max = 2;
+        let half = later(start, ACK_TIMEOUT / 2);
+        assert_eq!(choker.poll_transmit(half), vec![2]);
+
+        // the tick wakes up for the first message, which is the only one due
+        let (next, mids) = choker.rto_tick(later(start, ACK_TIMEOUT));
+        assert_eq!(mids, vec![1]);
+        assert_eq!(next, later(half, ACK_TIMEOUT));
+
+        let (_, mids) = choker.rto_tick(next);
+        assert_eq!(mids, vec![2]);
+    }
+
+    #[test]
+    fn reset_fails_the_message() {
+        let start = Instant::now();
+        let mut choker = sent(ChokerConfig::default(), &[1], start);
+
+        assert!(choker.on_reset(1, start));
+        assert!(!choker.on_reset(1, start));
+        assert!(choker.window().is_empty());
+        assert_eq!(failures(&mut choker), vec![(1, FailReason::Reset)]);
+    }
+
+    #[test]
+    fn fixed_window_survives_path_change() {
+        let mut choker: Choker = Choker::with_config(ChokerConfig {
+            fixed_window: true,
+            nstart: 4,
+            ..ChokerConfig::default()
+        });
+
+        choker.on_path_change();
+        assert_eq!(choker.window_max(), 4);
+    }
+
+    #[test]
+    fn expired_messages_fail() {
+        let mut choker = Choker::new();
+        let start = Instant::now();
+
+        choker
+            .enqueue_with_expiry(1, vec![0], later(start, Duration::from_secs(1)))
+            .unwrap();
+        choker.poll_transmit(start);
+        choker.rto_tick(later(start, Duration::from_secs(1)));
+
+        assert!(choker.window().is_empty());
+        assert_eq!(failures(&mut choker), vec![(1, FailReason::Expired)]);
+    }
+
+    #[test]
+    fn nothing_goes_out_while_unreachable() {
+        let mut choker = Choker::new();
+        let start = Instant::now();
+        choker.enqueue_non(1, vec![0]).unwrap();
+        choker.enqueue(2, vec![0]).unwrap();
+
+        choker.on_unreachable();
+        assert_eq!(choker.poll_non(start), None);
+        assert!(choker.poll_transmit(start).is_empty());
+        assert_eq!(choker.next_deadline(), choker.rto_end());
+
+        choker.on_reachable();
+        assert_eq!(choker.poll_non(start), Some((1, vec![0])));
+        assert_eq!(choker.poll_transmit(start), vec![2]);
+    }
+
+    #[test]
+    fn multicast_honors_pause_and_drain() {
+        let start = Instant::now();
+        let mut choker: Choker = Choker::with_config(ChokerConfig {
+            multicast: true,
+            ..ChokerConfig::default()
+        });
+        choker.enqueue(1, vec![0]).unwrap();
+
+        let until = later(start, Duration::from_secs(1000));
+        choker.pause_until(until);
+        let (next, mids) = choker.rto_tick(start);
+        assert!(mids.is_empty());
+        assert!(next <= until);
+
+        assert_eq!(choker.rto_tick(until).1, vec![1]);
+
+        choker.enqueue(2, vec![0]).unwrap();
+        choker.drain();
+        assert!(choker.rto_tick(later(until, DEFAULT_LEISURE)).1.is_empty());
+    }
+
+    #[test]
+    fn ended_pause_does_not_wake_a_held_queue() {
+        for hold in [Choker::drain, Choker::on_unreachable] {
+            let mut choker = Choker::new();
+            let start = Instant::now();
+            choker.enqueue(1, vec![0]).unwrap();
+
+            let until = later(start, Duration::from_secs(1));
+            choker.pause_until(until);
+            assert_eq!(choker.rto_tick(start).0, until);
+
+            hold(&mut choker);
+            let now = later(until, Duration::from_secs(1));
+            let (next, mids) = choker.rto_tick(now);
+
+            assert!(mids.is_empty());
+            assert!(next > now);
+            assert_eq!(choker.paused_until(), None);
+        }
+    }
+
+    #[test]
+    fn held_non_messages_do_not_wake_the_loop() {
+        let mut choker = Choker::new();
+        let start = Instant::now();
+        choker.enqueue_non(1, vec![0]).unwrap();
+        choker.rto_tick(start);
+
+        let until = later(start, Duration::from_secs(1000));
+        choker.pause_until(until);
+        assert_eq!(choker.next_deadline(), choker.rto_end().min(until));
+        assert!(choker.time_until_tick(start) > Duration::ZERO);
+        assert_eq!(choker.poll_non(start), None);
+
+        choker.drain();
+        assert_eq!(choker.next_deadline(), choker.rto_end());
+        assert!(choker.time_until_tick(start) > Duration::ZERO);
+        assert_eq!(choker.poll_non(until), None);
+    }
+
+    #[test]
+    fn rate_limit_holds_back_non_messages() {
+        let mut choker: Choker = Choker::with_config(ChokerConfig {
+            rate_limit: Some(RateLimit {
+                rate: 0.001,
+                burst: 1,
+            }),
+            non_con_every: None,
+            ..ChokerConfig::default()
+        });
+        let start = Instant::now();
+        for mid in 0..5 {
+            choker.enqueue_non(mid, vec![0]).unwrap();
+        }
+
+        let mut released = 0;
+        for secs in 0..15 {
+            let now = later(start, Duration::from_secs(secs * 3));
+            released += choker.poll_non(now).into_iter().count();
+        }
+
+        assert_eq!(released, 1);
+    }
+
+    #[test]
+    fn draining_holds_back_everything_queued() {
+        let mut choker = Choker::new();
+        let start = Instant::now();
+        choker.enqueue_non(1, vec![0]).unwrap();
+        choker.enqueue(2, vec![0]).unwrap();
+
+        choker.drain();
+        assert_eq!(choker.poll_non(start), None);
+        assert!(choker.poll_transmit(start).is_empty());
+        assert!(choker.drained());
+
+        let dropped = choker.reset(start);
+        assert_eq!(dropped, vec![(2, vec![0]), (1, vec![0])]);
+        assert!(!choker.drained());
+    }
+
+    #[test]
+    fn cancel_takes_back_queued_and_in_flight_messages() {
+        let start = Instant::now();
+        let mut choker = sent(ChokerConfig::default(), &[1], start);
+        choker.enqueue(2, vec![2]).unwrap();
+
+        assert_eq!(choker.cancel(2), CancelResult::Dequeued(vec![2]));
+        assert_eq!(choker.cancel(1), CancelResult::InFlight);
+        assert_eq!(choker.cancel(3), CancelResult::Unknown);
+
+        // not retransmitted, and gone without counting as a failure
+        assert!(choker.rto_tick(later(start, ACK_TIMEOUT)).1.is_empty());
+        assert!(choker.window().is_empty());
+        assert!(failures(&mut choker).is_empty());
+    }
+
+    #[test]
+    fn acked_messages_are_sampled_and_pruned() {
+        let start = Instant::now();
+        let mut choker = sent(ChokerConfig::default(), &[1], start);
+
+        let at = later(start, Duration::from_millis(100));
+        assert_eq!(choker.set_ack(1, at), AckResult::Acked);
+        assert_eq!(choker.set_ack(1, at), AckResult::DuplicateAck);
+        assert_eq!(choker.set_ack(2, at), AckResult::UnknownMid);
+
+        let outcome = choker.tick(later(at, Duration::from_millis(100)));
+        assert_eq!(outcome.completed, vec![1]);
+        assert!(choker.window().is_empty());
+        assert_eq!(choker.rtt_min(), Some(Duration::from_millis(100)));
+        assert_ne!(choker.rto(), ACK_TIMEOUT);
+
+        // the MID can't be reused within EXCHANGE_LIFETIME
+        assert_eq!(choker.enqueue(1, vec![0]), Err(EnqueueError::MidInUse));
+        assert_eq!(choker.set_ack(1, at), AckResult::DuplicateAck);
+    }
+
+    #[test]
+    fn reset_starts_the_window_at_nstart() {
+        let start = Instant::now();
+        let mut choker = sent(
+            ChokerConfig {
+                nstart: 4,
+                ..ChokerConfig::default()
+            },
+            &[1, 2],
+            start,
+        );
+        choker.on_unreachable();
+        assert_eq!(choker.window_max(), 1);
+
+        let dropped = choker.reset(start);
+        assert_eq!(dropped.len(), 2);
+        assert_eq!(choker.window_max(), 4);
+        assert!(!choker.is_unreachable());
+        assert!(choker.is_empty());
+    }
 }
@@ -0,0 +1,24 @@
+use std::time::{Duration, Instant};
+
+use crate::FailReason;
+
+// Told about what happens to the messages of a Choker as it happens, see Choker::set_observer
+//
+// For logging, metrics, or waking up whoever waits on a message, without polling,
+// every method does nothing unless implemented
+pub trait ChokerObserver {
+    // A message went out for the first time
+    fn on_transmit(&mut self, _mid: u16, _now: Instant) {}
+
+    // A message went out again, `transmissions` is its retransmission count by now
+    fn on_retransmit(&mut self, _mid: u16, _transmissions: u8, _now: Instant) {}
+
+    // A message was acked after `transmissions` retransmissions, `rtt` after its first transmission
+    fn on_ack(&mut self, _mid: u16, _rtt: Duration, _transmissions: u8) {}
+
+    // A message was given up on, its data comes from Choker::drain_failed
+    fn on_give_up(&mut self, _mid: u16, _reason: FailReason) {}
+
+    // window_max went from `before` to `after`
+    fn on_window_change(&mut self, _before: usize, _after: usize) {}
+}
@@ -0,0 +1,61 @@
+use std::{rc::Rc, sync::Arc};
+
+// What a Choker carries for every message, only its size is ever looked at,
+// for byte accounting, retransmission budgets and probing
+//
+// Whatever the application already holds its messages in can go in as is,
+// a pre-built CoAP packet, a shared buffer, or () when it keeps them itself
+pub trait Payload {
+    fn size(&self) -> usize;
+}
+
+impl Payload for Vec<u8> {
+    fn size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl Payload for Box<[u8]> {
+    fn size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl Payload for Arc<[u8]> {
+    fn size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl Payload for Rc<[u8]> {
+    fn size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl Payload for &[u8] {
+    fn size(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<const N: usize> Payload for [u8; N] {
+    fn size(&self) -> usize {
+        N
+    }
+}
+
+// the application keeps the data, the Choker only the MIDs
+impl Payload for () {
+    fn size(&self) -> usize {
+        0
+    }
+}
+
+// shares the buffer, so retransmissions and get_data never copy it
+#[cfg(feature = "bytes")]
+impl Payload for bytes::Bytes {
+    fn size(&self) -> usize {
+        self.len()
+    }
+}
@@ -0,0 +1,84 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// The state of a Choker at the end of one tick
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Record {
+    pub at: Instant,
+    pub srtt: Option<Duration>,
+    pub rttvar: Option<Duration>,
+    pub rto: Duration,
+    pub window_max: usize,
+}
+
+// Keeps the last `capacity` records, for plotting how the RTO and window evolve,
+// see Choker::set_recorder
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    pub capacity: usize,
+    pub records: VecDeque<Record>,
+}
+
+impl Recorder {
+    pub fn new(capacity: usize) -> Self {
+        Recorder {
+            capacity,
+            // grows as records come in, capacity can be far more than will ever be recorded
+            records: VecDeque::new(),
+        }
+    }
+
+    // Appends a record, dropping the oldest one when full
+    pub fn record(&mut self, record: Record) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        while self.records.len() >= self.capacity {
+            self.records.pop_front();
+        }
+
+        self.records.push_back(record);
+    }
+
+    // Takes the records made so far, oldest first
+    pub fn drain(&mut self) -> impl Iterator<Item = Record> + '_ {
+        self.records.drain(..)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(window_max: usize) -> Record {
+        Record {
+            at: Instant::now(),
+            srtt: None,
+            rttvar: None,
+            rto: Duration::from_secs(2),
+            window_max,
+        }
+    }
+
+    #[test]
+    fn keeps_the_last_records() {
+        let mut recorder = Recorder::new(2);
+        for window_max in 1..=3 {
+            recorder.record(record(window_max));
+        }
+
+        let kept: Vec<_> = recorder.drain().map(|r| r.window_max).collect();
+        assert_eq!(kept, vec![2, 3]);
+    }
+
+    #[test]
+    fn unbounded_capacity_allocates_lazily() {
+        let mut recorder = Recorder::new(usize::MAX);
+        recorder.record(record(1));
+
+        assert_eq!(recorder.records.len(), 1);
+    }
+}
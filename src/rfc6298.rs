@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use crate::{
+    bias,
+    rto::{secs, ALPHA, BETA},
+    RtoEstimator, ACK_TIMEOUT,
+};
+
+// Classic RFC 6298 SRTT/RTTVAR estimation
+//
+// Only samples of messages that weren't retransmitted are taken (Karn),
+// and the RTO is SRTT + max(G, 4 * RTTVAR), bounded by min_rto and max_rto
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rfc6298 {
+    pub srtt: Option<f64>,
+    pub rttvar: f64,
+
+    pub rto: Duration,
+
+    // Clock granularity G
+    pub granularity: Duration,
+
+    pub min_rto: Duration,
+    pub max_rto: Duration,
+}
+
+impl Rfc6298 {
+    pub fn new() -> Self {
+        Rfc6298 {
+            srtt: None,
+            rttvar: 0.0,
+            rto: ACK_TIMEOUT,
+            granularity: Duration::from_millis(1),
+            // RFC 6298 rounds anything lower up to 1 second
+            min_rto: Duration::from_secs(1),
+            max_rto: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Default for Rfc6298 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RtoEstimator for Rfc6298 {
+    fn on_ack(&mut self, sample: Duration, transmissions: u8) {
+        if transmissions > 0 {
+            return;
+        }
+
+        let r = sample.as_secs_f64();
+
+        if let Some(srtt) = &mut self.srtt {
+            self.rttvar = bias(self.rttvar, BETA, (*srtt - r).abs());
+            *srtt = bias(*srtt, ALPHA, r);
+        } else {
+            self.srtt = Some(r);
+            self.rttvar = r / 2.0;
+        }
+
+        let srtt = self.srtt.unwrap_or(r);
+        let k = f64::max(self.granularity.as_secs_f64(), 4.0 * self.rttvar);
+
+        self.rto = Duration::try_from_secs_f64(srtt + k)
+            .unwrap_or(self.max_rto)
+            .max(self.min_rto)
+            .min(self.max_rto);
+    }
+
+    fn current(&self) -> Duration {
+        self.rto
+    }
+
+    fn srtt(&self) -> Option<Duration> {
+        self.srtt.map(secs)
+    }
+
+    fn rttvar(&self) -> Option<Duration> {
+        self.srtt.map(|_| secs(self.rttvar))
+    }
+
+    fn on_congestion(&mut self) {
+        self.rto = (self.rto * 2).min(self.max_rto);
+    }
+
+    fn reset(&mut self) {
+        self.srtt = None;
+        self.rttvar = 0.0;
+        self.rto = ACK_TIMEOUT;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_sample_sets_srtt_and_rttvar() {
+        let mut rfc = Rfc6298::new();
+        rfc.on_ack(Duration::from_millis(500), 0);
+
+        assert_eq!(rfc.srtt(), Some(Duration::from_millis(500)));
+        assert_eq!(rfc.rttvar(), Some(Duration::from_millis(250)));
+        assert_eq!(rfc.current(), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn rto_is_bounded() {
+        let mut rfc = Rfc6298::new();
+        rfc.on_ack(Duration::from_millis(100), 0);
+        assert_eq!(rfc.current(), rfc.min_rto);
+
+        for _ in 0..8 {
+            rfc.on_congestion();
+        }
+        assert_eq!(rfc.current(), rfc.max_rto);
+    }
+
+    #[test]
+    fn retransmitted_samples_are_ignored() {
+        let mut rfc = Rfc6298::new();
+        rfc.on_ack(Duration::from_millis(500), 1);
+
+        assert_eq!(rfc.srtt(), None);
+        assert_eq!(rfc.current(), ACK_TIMEOUT);
+    }
+}
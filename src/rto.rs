@@ -0,0 +1,895 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::{RtoEstimator, ACK_TIMEOUT};
+
+pub(crate) const ALPHA: f64 = 0.125;
+pub(crate) const BETA: f64 = 0.25;
+const W_STRONG: f64 = 0.5;
+const W_WEAK: f64 = 0.25;
+
+// EWMA gain of the sample to sample variation adaptive_alpha goes by
+const JITTER_GAIN: f64 = 0.25;
+// Relative variation from which on adaptive_alpha is at its lowest
+const JITTER_HIGH: f64 = 0.2;
+
+// Variable backoff factors, for small, medium, and large RTOs respectively
+const VBF_SMALL: f64 = 3.0;
+const VBF_MEDIUM: f64 = 2.0;
+const VBF_LARGE: f64 = 1.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Variant {
+    // CoCoA as in draft-ietf-core-cocoa
+    CoCoA,
+    // CoCoA+, weighs weak samples down by their retransmissions,
+    // and ages the estimators along with the RTO
+    CoCoAPlus,
+}
+
+// How the samples of a single tick are weighed against each other
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AckWeighting {
+    // The gains are divided by the samples in the tick, so a tick moves the RTO as much as one sample
+    PerTick,
+    // Every sample is applied with the full gains, one after the other
+    PerSample,
+    // The gains are divided by what this returns for the number of samples in the tick,
+    // anything below 1 counts as 1
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Custom(fn(usize) -> f64),
+}
+
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RtoConfig {
+    pub variant: Variant,
+
+    // RTO of a fresh estimator, ACK_TIMEOUT by default
+    pub initial_rto: Duration,
+
+    // RTOs below this back off by VBF_SMALL
+    pub vbf_lower: Duration,
+    // RTOs above this back off by VBF_LARGE
+    pub vbf_upper: Duration,
+
+    // RTOs below this are considered small for aging
+    pub aging_small: Duration,
+    // Multiples of a small RTO without updates before it is aged
+    pub aging_small_after: u32,
+    // Multiples of an RTO above initial_rto without updates before it is aged
+    pub aging_large_after: u32,
+
+    // Bounds the RTO is clamped to whenever it is updated
+    pub min_rto: Duration,
+    pub max_rto: Duration,
+
+    // Clock granularity G of the timers the RTO ends up in, the variance term is at least G
+    // as in RFC 6298, and the RTO never goes below it, zero for none
+    pub granularity: Duration,
+
+    // Samples from messages with up to this many retransmissions feed the weak estimator,
+    // samples with more are ignored
+    pub weak_max_transmissions: u8,
+
+    // Karn's algorithm, ignore all samples from retransmitted messages,
+    // leaving the weak estimator untouched
+    pub karn: bool,
+
+    // Exchanges expected to run in parallel with this endpoint,
+    // the blind initial RTO is initial_rto divided by this
+    pub parallel_exchanges: u32,
+
+    // EWMA gains of the estimators and their variances, in (0, 1]
+    pub alpha: f64,
+    pub beta: f64,
+
+    // Adapt alpha of each estimator to how much its samples vary from one to the next,
+    // a stable RTT raises it up to four times alpha, so a level shift like a path change
+    // is followed quickly, while high variance lowers it down to half of alpha to smooth it out
+    pub adaptive_alpha: bool,
+
+    // Weights of the strong and weak estimators in the RTO, in (0, 1],
+    // a w_weak of 0 disables weak updates entirely
+    pub w_strong: f64,
+    pub w_weak: f64,
+
+    // Ignore weak samples while a strong sample was seen within this many ticks
+    pub weak_holdoff: Option<u32>,
+
+    pub ack_weighting: AckWeighting,
+
+    // Pull the weak estimator toward the strong one by this gain every tick with a strong sample,
+    // once no weak samples came in for weak_decay_after ticks, so it doesn't stay inflated
+    // after congestion passes, in [0, 1], 0 disables it
+    pub weak_decay: f64,
+    pub weak_decay_after: u32,
+
+    // Drop strong samples that stray too far from the recent ones, off by default
+    pub outlier_filter: Option<OutlierFilter>,
+}
+
+// A median/MAD filter in front of the strong estimator
+//
+// A sample more than `threshold` median absolute deviations away from the median
+// of the last `window` strong samples is rejected, so one delayed ACK doesn't throw the RTO off
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OutlierFilter {
+    pub window: usize,
+    pub threshold: f64,
+}
+
+impl RtoConfig {
+    pub fn validate(&self) -> Result<(), RtoConfigError> {
+        let unit = |v: f64| v > 0.0 && v <= 1.0;
+
+        if !unit(self.alpha) {
+            Err(RtoConfigError::OutOfRange("alpha"))
+        } else if !unit(self.beta) {
+            Err(RtoConfigError::OutOfRange("beta"))
+        } else if !unit(self.w_strong) {
+            Err(RtoConfigError::OutOfRange("w_strong"))
+        } else if !(unit(self.w_weak) || self.w_weak == 0.0) {
+            Err(RtoConfigError::OutOfRange("w_weak"))
+        } else if self.min_rto > self.max_rto {
+            Err(RtoConfigError::OutOfRange("min_rto"))
+        } else if self.vbf_lower > self.vbf_upper {
+            Err(RtoConfigError::OutOfRange("vbf_lower"))
+        } else if !(unit(self.weak_decay) || self.weak_decay == 0.0) {
+            Err(RtoConfigError::OutOfRange("weak_decay"))
+        } else if matches!(self.outlier_filter, Some(f) if f.window < 3) {
+            Err(RtoConfigError::OutOfRange("outlier_filter.window"))
+        } else if matches!(self.outlier_filter, Some(f) if f.threshold.is_nan() || f.threshold <= 0.0)
+        {
+            Err(RtoConfigError::OutOfRange("outlier_filter.threshold"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtoConfigError {
+    // The named field is outside of its allowed range
+    OutOfRange(&'static str),
+}
+
+impl Default for RtoConfig {
+    fn default() -> Self {
+        RtoConfig {
+            variant: Variant::CoCoA,
+            initial_rto: ACK_TIMEOUT,
+            vbf_lower: Duration::from_secs(1),
+            vbf_upper: Duration::from_secs(3),
+            aging_small: Duration::from_secs(1),
+            aging_small_after: 16,
+            aging_large_after: 4,
+            // CoCoA does not truncate the RTO from below
+            min_rto: Duration::ZERO,
+            max_rto: Duration::from_secs(60),
+            granularity: Duration::ZERO,
+            weak_max_transmissions: 2,
+            karn: false,
+            parallel_exchanges: 1,
+            alpha: ALPHA,
+            beta: BETA,
+            adaptive_alpha: false,
+            w_strong: W_STRONG,
+            w_weak: W_WEAK,
+            weak_holdoff: None,
+            ack_weighting: AckWeighting::PerTick,
+            weak_decay: 0.0,
+            weak_decay_after: 4,
+            outlier_filter: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RTO {
+    pub rto: Duration,
+
+    pub var_strong: f64,
+    pub strong: f64,
+
+    pub var_weak: f64,
+    pub weak: f64,
+
+    // Last time a sample updated the RTO, for aging
+    pub updated: Instant,
+
+    // Ticks since the last strong sample
+    pub strong_age: u32,
+    // Ticks since the last weak sample
+    pub weak_age: u32,
+
+    // Last strong samples in seconds, for the outlier filter
+    pub history: VecDeque<f64>,
+
+    // Smoothed variation between consecutive samples of each estimator, relative and in [0, 1],
+    // and the sample it was last taken from, see RtoConfig::adaptive_alpha
+    pub jitter_strong: f64,
+    pub jitter_weak: f64,
+    pub last_strong: Option<f64>,
+    pub last_weak: Option<f64>,
+
+    // How the estimator was at the last tick, while early samples moved it since,
+    // see RtoEstimator::on_early_samples
+    pub before_tick: Option<Box<RTO>>,
+
+    pub config: RtoConfig,
+}
+
+impl RTO {
+    pub fn new() -> Self {
+        Self::with_config(RtoConfig::default())
+    }
+
+    // Like with_config, but rejects configs that don't validate
+    pub fn try_with_config(config: RtoConfig) -> Result<Self, RtoConfigError> {
+        config.validate()?;
+
+        Ok(Self::with_config(config))
+    }
+
+    pub fn with_config(config: RtoConfig) -> Self {
+        RTO {
+            rto: config.initial_rto / config.parallel_exchanges.max(1),
+            var_strong: 0.2,
+            strong: config.initial_rto.as_secs_f64(),
+            var_weak: 0.2,
+            weak: config.initial_rto.as_secs_f64(),
+            updated: Instant::now(),
+            strong_age: u32::MAX,
+            weak_age: u32::MAX,
+            history: VecDeque::new(),
+            jitter_strong: 0.0,
+            jitter_weak: 0.0,
+            last_strong: None,
+            last_weak: None,
+            before_tick: None,
+            config,
+        }
+    }
+
+    // Warm start from a previously measured SRTT and RTTVAR to the same peer,
+    // instead of the conservative initial RTO
+    pub fn from_history(srtt: Duration, rttvar: Duration) -> Self {
+        Self::from_history_with_config(RtoConfig::default(), srtt, rttvar)
+    }
+
+    pub fn from_history_with_config(config: RtoConfig, srtt: Duration, rttvar: Duration) -> Self {
+        let mut rto = Self::with_config(config);
+
+        rto.strong = srtt.as_secs_f64();
+        rto.var_strong = rttvar.as_secs_f64();
+        rto.weak = rto.strong;
+        rto.var_weak = rto.var_strong;
+        // counts as a strong sample, so a weak_holdoff doesn't wait on a fresh one
+        rto.strong_age = 0;
+        // persisted values can be anything, don't overflow on them
+        rto.rto = rto.bound(srtt.saturating_add(rttvar.saturating_mul(4).max(config.granularity)));
+
+        rto
+    }
+
+    // Throws away all RTT history, back to a fresh estimator with the same config
+    pub fn reset_to_default(&mut self) {
+        *self = Self::with_config(self.config);
+    }
+
+    pub fn snapshot(&self) -> RtoState {
+        RtoState {
+            strong: self.strong,
+            var_strong: self.var_strong,
+            weak: self.weak,
+            var_weak: self.var_weak,
+            rto: self.rto,
+        }
+    }
+
+    // Smoothed RTT of the weak estimator, see RtoEstimator::srtt for the strong one
+    pub fn srtt_weak(&self) -> Duration {
+        secs(self.weak)
+    }
+
+    pub fn rttvar_strong(&self) -> Duration {
+        secs(self.var_strong)
+    }
+
+    pub fn rttvar_weak(&self) -> Duration {
+        secs(self.var_weak)
+    }
+
+    // The backoff factor for an exchange starting at the current RTO
+    pub fn vbf(&self) -> f64 {
+        if self.rto < self.config.vbf_lower {
+            VBF_SMALL
+        } else if self.rto > self.config.vbf_upper {
+            VBF_LARGE
+        } else {
+            VBF_MEDIUM
+        }
+    }
+
+    // Timeout of an exchange started now after `n` of its transmissions got lost,
+    // for working out worst-case deadlines before even sending
+    pub fn predict_after_losses(&self, n: u8) -> Duration {
+        let factor = self.vbf();
+
+        (1..=n).fold(self.rto, |timeout, transmissions| {
+            self.next_timeout(transmissions, timeout, factor)
+        })
+    }
+
+    // Feeds an RTT sample into the estimators, with the gains divided by `weighted_against`,
+    // returns whether it was used to update the RTO
+    pub fn calc(&mut self, transmissions: u8, rtt: Duration, weighted_against: f64) -> bool {
+        let mut secs = self.rto.as_secs_f64();
+        let rtt = rtt.as_secs_f64();
+        // weighing against nothing would divide the gains by zero
+        let weight = weighted_against.max(1.0);
+        let granularity = self.config.granularity.as_secs_f64();
+
+        if transmissions == 0 && self.is_outlier(rtt) {
+            return false;
+        }
+
+        let before = (
+            (self.strong, self.var_strong, self.weak, self.var_weak),
+            (self.jitter_strong, self.last_strong),
+            (self.jitter_weak, self.last_weak),
+        );
+
+        if transmissions == 0 {
+            // instant ACK
+            self.strong_age = 0;
+
+            // Update self.var_strong with a 4th of |self.strong - rtt|
+            self.var_strong = bias(
+                self.var_strong,
+                self.config.beta / weight,
+                (self.strong - rtt).abs(),
+            );
+
+            if self.config.adaptive_alpha {
+                self.jitter_strong = jitter(self.jitter_strong, self.last_strong, rtt, weight);
+                self.last_strong = Some(rtt);
+            }
+
+            // Bias self.strong towards rtt by alpha, an 8th by default
+            let alpha = self.alpha(self.jitter_strong);
+            self.strong = bias(self.strong, alpha / weight, rtt);
+
+            // Update secs with a bias of (self.strong + max(G, 4*self.var_strong))
+            secs = bias(
+                secs,
+                self.config.w_strong / weight,
+                self.strong + (4.0 * self.var_strong).max(granularity),
+            );
+        } else if self.weak_enabled() && transmissions <= self.config.weak_max_transmissions {
+            // took a few retransmissions
+            self.weak_age = 0;
+
+            // Update self.var_weak with a 4th of |self.weak - rtt|
+            self.var_weak = bias(
+                self.var_weak,
+                self.config.beta / weight,
+                (self.weak - rtt).abs(),
+            );
+
+            if self.config.adaptive_alpha {
+                self.jitter_weak = jitter(self.jitter_weak, self.last_weak, rtt, weight);
+                self.last_weak = Some(rtt);
+            }
+
+            let alpha = self.alpha(self.jitter_weak);
+            self.weak = bias(self.weak, alpha / weight, rtt);
+
+            let mut w_weak = self.config.w_weak;
+            if self.config.variant == Variant::CoCoAPlus {
+                // the more retransmissions, the more ambiguous the sample
+                w_weak /= transmissions as f64;
+            }
+
+            secs = bias(
+                secs,
+                w_weak / weight,
+                self.weak + self.var_weak.max(granularity),
+            );
+        } else {
+            // can't tell which transmission got ACKed
+            return false;
+        }
+
+        // a gain that isn't validated can turn the estimators into NaN or infinity,
+        // don't let it take down the RTO for good
+        let finite = [self.strong, self.var_strong, self.weak, self.var_weak, secs]
+            .iter()
+            .all(|v| v.is_finite());
+        debug_assert!(
+            finite,
+            "RTO estimators went non-finite, validate the RtoConfig"
+        );
+        if !finite {
+            (
+                (self.strong, self.var_strong, self.weak, self.var_weak),
+                (self.jitter_strong, self.last_strong),
+                (self.jitter_weak, self.last_weak),
+            ) = before;
+            return false;
+        }
+
+        self.rto = self.bound(Duration::try_from_secs_f64(secs).unwrap_or_default());
+
+        true
+    }
+
+    // Feeds the samples of a tick weighted per ack_weighting, returns whether any updated the RTO
+    fn weigh(&mut self, samples: &[(Duration, u8)]) -> bool {
+        let weighted_against = match self.config.ack_weighting {
+            AckWeighting::PerTick => samples.len() as f64,
+            AckWeighting::PerSample => 1.0,
+            AckWeighting::Custom(weigh) => weigh(samples.len()),
+        };
+
+        let mut updated = false;
+        for &(rtt, transmissions) in samples {
+            updated |= self.calc(transmissions, rtt, weighted_against);
+        }
+
+        updated
+    }
+
+    // Call this when no samples updated the RTO for a while
+    //
+    // Per CoCoA, a small RTO that went without updates for `aging_small_after` times itself
+    // is doubled, and a large RTO that went without updates for `aging_large_after` times itself
+    // is pulled halfway back to the initial RTO
+    //
+    // CoCoA+ ages the strong and weak estimators along with it,
+    // so the next sample doesn't drag the RTO right back to where it was
+    pub fn age(&mut self, now: Instant) {
+        let idle = now.saturating_duration_since(self.updated);
+
+        // an RTO too large to multiply can't have been idle for that long
+        let idle_for = |after| {
+            self.rto
+                .checked_mul(after)
+                .is_some_and(|threshold| idle >= threshold)
+        };
+
+        let aged = if self.rto < self.config.aging_small && idle_for(self.config.aging_small_after)
+        {
+            self.bound(self.rto.saturating_mul(2))
+        } else if self.rto > self.config.initial_rto && idle_for(self.config.aging_large_after) {
+            self.bound(self.config.initial_rto.saturating_add(self.rto) / 2)
+        } else {
+            return;
+        };
+
+        if self.config.variant == Variant::CoCoAPlus && !self.rto.is_zero() {
+            let factor = aged.as_secs_f64() / self.rto.as_secs_f64();
+
+            self.strong *= factor;
+            self.weak *= factor;
+        }
+
+        self.rto = aged;
+        self.updated = now;
+    }
+
+    // Call this once every tick, after feeding it that tick's samples
+    pub fn tick(&mut self) {
+        let decay = self.config.weak_decay;
+        if decay > 0.0 && self.strong_age == 0 && self.weak_age >= self.config.weak_decay_after {
+            self.weak = bias(self.weak, decay, self.strong);
+            self.var_weak = bias(self.var_weak, decay, self.var_strong);
+        }
+
+        self.strong_age = self.strong_age.saturating_add(1);
+        self.weak_age = self.weak_age.saturating_add(1);
+    }
+
+    // Checks a strong sample against the outlier filter and remembers it
+    //
+    // Rejected samples are remembered too, so a lasting change in RTT gets through once it's the norm
+    fn is_outlier(&mut self, rtt: f64) -> bool {
+        let Some(filter) = self.config.outlier_filter else {
+            return false;
+        };
+
+        // a window that wasn't validated can't hold anything to compare against
+        let outlier = filter.window > 0
+            && !self.history.is_empty()
+            && self.history.len() >= filter.window
+            && {
+                let mid = median(self.history.iter().copied());
+                let mad = median(self.history.iter().map(|s| (s - mid).abs()));
+
+                // identical samples have no deviation, allow for clock granularity
+                (rtt - mid).abs() > filter.threshold * mad.max(0.001)
+            };
+
+        self.history.push_back(rtt);
+        while self.history.len() > filter.window {
+            self.history.pop_front();
+        }
+
+        outlier
+    }
+
+    // The EWMA gain for an estimator with the given variation, see RtoConfig::adaptive_alpha
+    fn alpha(&self, jitter: f64) -> f64 {
+        if !self.config.adaptive_alpha {
+            return self.config.alpha;
+        }
+
+        // four times alpha without any variation, down to half of it at JITTER_HIGH
+        let high = (jitter / JITTER_HIGH).min(1.0);
+        (self.config.alpha * (4.0 - 3.5 * high)).min(1.0)
+    }
+
+    fn weak_enabled(&self) -> bool {
+        let held_off = matches!(self.config.weak_holdoff, Some(ticks) if self.strong_age < ticks);
+
+        !self.config.karn && self.config.w_weak > 0.0 && !held_off
+    }
+
+    fn bound(&self, rto: Duration) -> Duration {
+        rto.max(self.config.min_rto)
+            .max(self.config.granularity)
+            .min(self.config.max_rto)
+    }
+}
+
+// Estimator state of an RTO at one point in time, estimators in seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RtoState {
+    pub strong: f64,
+    pub var_strong: f64,
+    pub weak: f64,
+    pub var_weak: f64,
+    pub rto: Duration,
+}
+
+// Instants don't survive a restart, so `updated` is persisted as the time since it
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PersistedRto {
+    rto: Duration,
+    var_strong: f64,
+    strong: f64,
+    var_weak: f64,
+    weak: f64,
+    since_updated: Duration,
+    strong_age: u32,
+    weak_age: u32,
+    config: RtoConfig,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for RTO {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PersistedRto {
+            rto: self.rto,
+            var_strong: self.var_strong,
+            strong: self.strong,
+            var_weak: self.var_weak,
+            weak: self.weak,
+            since_updated: self.updated.elapsed(),
+            strong_age: self.strong_age,
+            weak_age: self.weak_age,
+            config: self.config,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for RTO {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let p = PersistedRto::deserialize(deserializer)?;
+        let now = Instant::now();
+
+        Ok(RTO {
+            rto: p.rto,
+            var_strong: p.var_strong,
+            strong: p.strong,
+            var_weak: p.var_weak,
+            weak: p.weak,
+            updated: now.checked_sub(p.since_updated).unwrap_or(now),
+            strong_age: p.strong_age,
+            weak_age: p.weak_age,
+            // the filter and variations refill from fresh samples
+            history: VecDeque::new(),
+            jitter_strong: 0.0,
+            jitter_weak: 0.0,
+            last_strong: None,
+            last_weak: None,
+            before_tick: None,
+            config: p.config,
+        })
+    }
+}
+
+impl Default for RTO {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The outlier history is only counted, it can be long and says little on its own
+impl std::fmt::Debug for RTO {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RTO")
+            .field("rto", &self.rto)
+            .field("strong", &self.strong)
+            .field("var_strong", &self.var_strong)
+            .field("weak", &self.weak)
+            .field("var_weak", &self.var_weak)
+            .field("strong_age", &self.strong_age)
+            .field("weak_age", &self.weak_age)
+            .field("history", &self.history.len())
+            .field("jitter_strong", &self.jitter_strong)
+            .field("jitter_weak", &self.jitter_weak)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl RtoEstimator for RTO {
+    fn on_ack(&mut self, sample: Duration, transmissions: u8) {
+        self.calc(transmissions, sample, 1.0);
+    }
+
+    // Takes the early samples in over again from the last tick every time,
+    // so they're weighed against each other as on_tick would
+    fn on_early_samples(&mut self, samples: &[(Duration, u8)]) {
+        let before = self
+            .before_tick
+            .take()
+            .unwrap_or_else(|| Box::new(self.clone()));
+
+        *self = (*before).clone();
+        self.weigh(samples);
+        self.before_tick = Some(before);
+    }
+
+    // Samples of a tick are weighted per ack_weighting, and aging kicks in on ticks without any
+    fn on_tick(&mut self, samples: &[(Duration, u8)], now: Instant) {
+        // the early samples are among these
+        if let Some(before) = self.before_tick.take() {
+            *self = *before;
+        }
+
+        let updated = self.weigh(samples);
+
+        if updated {
+            self.updated = now;
+        } else {
+            self.age(now);
+        }
+
+        self.tick();
+    }
+
+    fn current(&self) -> Duration {
+        self.rto
+    }
+
+    fn backoff_factor(&self) -> f64 {
+        self.vbf()
+    }
+
+    fn srtt(&self) -> Option<Duration> {
+        Some(secs(self.strong))
+    }
+
+    fn rttvar(&self) -> Option<Duration> {
+        Some(secs(self.var_strong))
+    }
+
+    fn reset(&mut self) {
+        self.reset_to_default();
+    }
+
+    fn on_congestion(&mut self) {
+        // not to be undone by the next tick
+        if let Some(before) = &mut self.before_tick {
+            before.on_congestion();
+        }
+
+        let backed_off = Duration::try_from_secs_f64(self.rto.as_secs_f64() * self.vbf());
+        self.rto = self.bound(backed_off.unwrap_or(self.config.max_rto));
+    }
+}
+
+// Estimator values are kept as seconds, anything that isn't a valid duration reads as zero
+pub(crate) fn secs(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs).unwrap_or_default()
+}
+
+// The smoothed variation `jitter` after a sample of `rtt` following one of `last`,
+// with the gain divided by `weight`
+fn jitter(jitter: f64, last: Option<f64>, rtt: f64, weight: f64) -> f64 {
+    match last {
+        Some(last) if last.max(rtt) > 0.0 => bias(
+            jitter,
+            JITTER_GAIN / weight,
+            (rtt - last).abs() / last.max(rtt),
+        ),
+        _ => jitter,
+    }
+}
+
+fn median(values: impl Iterator<Item = f64>) -> f64 {
+    let mut values: Vec<f64> = values.collect();
+    values.sort_by(f64::total_cmp);
+
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+// a gets `1 - weight` influence, b gets `weight` influence
+pub fn bias(a: f64, weight: f64, b: f64) -> f64 {
+    debug_assert!(weight.is_finite(), "bias weight must be finite");
+
+    ((1.0 - weight) * a) + (b * weight)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Strong samples it takes after a step from 100ms to 1s until the SRTT is within 10% of 1s
+    fn samples_to_converge(adaptive_alpha: bool) -> usize {
+        let mut rto = RTO::with_config(RtoConfig {
+            adaptive_alpha,
+            ..RtoConfig::default()
+        });
+
+        for _ in 0..100 {
+            rto.calc(0, Duration::from_millis(100), 1.0);
+        }
+
+        (1..1000)
+            .find(|_| {
+                rto.calc(0, Duration::from_secs(1), 1.0);
+                rto.strong > 0.9
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn adaptive_alpha_converges_faster_after_path_change() {
+        let fixed = samples_to_converge(false);
+        let adaptive = samples_to_converge(true);
+
+        // at least twice as fast
+        assert!(adaptive * 2 <= fixed, "adaptive {adaptive}, fixed {fixed}");
+    }
+
+    #[test]
+    fn adaptive_alpha_smooths_out_noise() {
+        let mut rto = RTO::with_config(RtoConfig {
+            adaptive_alpha: true,
+            ..RtoConfig::default()
+        });
+
+        // alternating around 100ms
+        for i in 0..200 {
+            let rtt = if i % 2 == 0 { 80 } else { 120 };
+            rto.calc(0, Duration::from_millis(rtt), 1.0);
+        }
+
+        assert_eq!(rto.alpha(rto.jitter_strong), ALPHA / 2.0);
+    }
+
+    #[test]
+    fn adaptive_alpha_follows_a_stable_rtt() {
+        let mut rto = RTO::with_config(RtoConfig {
+            adaptive_alpha: true,
+            ..RtoConfig::default()
+        });
+
+        for _ in 0..20 {
+            rto.calc(0, Duration::from_millis(100), 1.0);
+        }
+
+        assert_eq!(rto.jitter_strong, 0.0);
+        assert_eq!(rto.alpha(rto.jitter_strong), ALPHA * 4.0);
+    }
+
+    #[test]
+    fn fixed_alpha_does_not_track_variation() {
+        let mut rto = RTO::new();
+
+        for i in 0..20 {
+            let rtt = if i % 2 == 0 { 80 } else { 120 };
+            rto.calc(0, Duration::from_millis(rtt), 1.0);
+        }
+
+        assert_eq!(rto.jitter_strong, 0.0);
+        assert_eq!(rto.last_strong, None);
+        assert_eq!(rto.alpha(rto.jitter_strong), ALPHA);
+    }
+
+    #[test]
+    fn congestion_saturates_at_max_rto() {
+        let mut rto = RTO::with_config(RtoConfig {
+            max_rto: Duration::MAX,
+            ..RtoConfig::default()
+        });
+
+        for _ in 0..200 {
+            rto.on_congestion();
+        }
+        assert_eq!(rto.current(), Duration::MAX);
+    }
+
+    #[test]
+    fn aging_a_huge_rto_does_not_panic() {
+        let mut rto = RTO::with_config(RtoConfig {
+            max_rto: Duration::MAX,
+            ..RtoConfig::default()
+        });
+        let now = rto.updated;
+
+        rto.rto = Duration::MAX / 2;
+        rto.age(now + Duration::from_secs(3600));
+        assert_eq!(rto.current(), Duration::MAX / 2);
+
+        rto.rto = Duration::MAX;
+        rto.age(now + Duration::from_secs(3600));
+        assert_eq!(rto.current(), Duration::MAX);
+    }
+
+    #[test]
+    fn empty_outlier_window_does_not_panic() {
+        let mut rto = RTO::with_config(RtoConfig {
+            outlier_filter: Some(OutlierFilter {
+                window: 0,
+                threshold: 3.0,
+            }),
+            ..RtoConfig::default()
+        });
+
+        assert!(rto.calc(0, Duration::from_millis(100), 1.0));
+        assert!(rto.calc(0, Duration::from_millis(100), 1.0));
+    }
+
+    #[test]
+    fn outlier_filter_rejects_a_lone_spike() {
+        let mut rto = RTO::with_config(RtoConfig {
+            outlier_filter: Some(OutlierFilter {
+                window: 5,
+                threshold: 3.0,
+            }),
+            ..RtoConfig::default()
+        });
+
+        for rtt in [100, 102, 98, 101, 99] {
+            assert!(rto.calc(0, Duration::from_millis(rtt), 1.0));
+        }
+
+        assert!(!rto.calc(0, Duration::from_secs(5), 1.0));
+    }
+
+    #[test]
+    fn from_history_saturates() {
+        let rto = RTO::from_history(Duration::MAX, Duration::MAX);
+
+        assert_eq!(rto.current(), RtoConfig::default().max_rto);
+    }
+}